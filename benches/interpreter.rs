@@ -0,0 +1,75 @@
+//! Runs every program in `benches/programs` a few times and prints how long
+//! each run took. Use `cargo bench`, optionally followed by `-- <name>` to
+//! run only programs whose name contains `<name>` and by `--optimize` to
+//! enable the optimizer. `LOX_BENCH_RUNS` sets the number of runs.
+
+use std::{
+    cell::RefCell,
+    env, fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crafting_interpreters::{
+    environment::Environment, interpreter::Interpreter, sandbox::Capabilities, Lox,
+};
+
+fn main() {
+    let runs: usize = env::var("LOX_BENCH_RUNS")
+        .ok()
+        .and_then(|runs| runs.parse().ok())
+        .unwrap_or(5);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let optimize = args.iter().any(|arg| arg == "--optimize");
+    let filter = args.iter().find(|arg| !arg.starts_with('-'));
+
+    println!(
+        "{:<16} {:>10} {:>10} {:>10}",
+        "program", "min", "median", "max"
+    );
+    for path in programs() {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        if filter.is_some_and(|filter| !name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let mut times: Vec<_> = (0..runs.max(1)).map(|_| run(&path, optimize)).collect();
+        times.sort();
+        println!(
+            "{:<16} {:>10} {:>10} {:>10}",
+            name,
+            millis(times[0]),
+            millis(times[times.len() / 2]),
+            millis(times[times.len() - 1])
+        );
+    }
+}
+
+fn programs() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/programs");
+    let mut programs: Vec<_> = fs::read_dir(dir)
+        .expect("benches/programs should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// Times one run in a fresh interpreter, including scanning and parsing.
+fn run(path: &Path, optimize: bool) -> Duration {
+    let mut lox = Lox::default();
+    lox.set_optimize(optimize);
+    let mut interpreter = Interpreter::new();
+    interpreter.capabilities = Capabilities::ALL;
+    let environment = Rc::new(RefCell::new(Environment::global()));
+
+    let start = Instant::now();
+    lox.run_file(path, &mut interpreter, &environment);
+    start.elapsed()
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.)
+}
@@ -0,0 +1,15 @@
+#![no_main]
+
+//! Feeds arbitrary text through the scanner and the parser. Syntax errors
+//! must be reported and recovered from, never panic or loop forever.
+
+use crafting_interpreters::{parser::Parser, scanner::Scanner, Lox};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data).into_owned();
+    let mut lox = Lox::default();
+    lox.set_diagnostics(std::io::sink());
+    let tokens = Scanner::new(source).scan_tokens(&mut lox);
+    Parser::new(tokens).parse(&mut lox);
+});
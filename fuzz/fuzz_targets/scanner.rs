@@ -0,0 +1,14 @@
+#![no_main]
+
+//! Feeds arbitrary text to the scanner. It must report bad input as errors
+//! instead of panicking.
+
+use crafting_interpreters::{scanner::Scanner, Lox};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data).into_owned();
+    let mut lox = Lox::default();
+    lox.set_diagnostics(std::io::sink());
+    Scanner::new(source).scan_tokens(&mut lox);
+});
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+
+use crate::{
+    expr::Expr,
+    stmt::{FunctionDecl, Stmt},
+    symbol::Symbol,
+    token::Token,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeclarationKind {
+    Variable,
+    Function,
+    Parameter,
+    Module,
+}
+
+/// A name introduced by `var`, `fun`, `import`, `catch` or a parameter list.
+#[derive(Debug)]
+pub struct Declaration {
+    pub name: Token,
+    pub kind: DeclarationKind,
+    /// Source-like summary, e.g. `fun add(a, b)`, `var count: number` or
+    /// `name: string` for a parameter.
+    pub detail: String,
+    /// Index of the function declaration this one is inside of, if any.
+    pub parent: Option<usize>,
+}
+
+/// A variable read or assigned.
+#[derive(Debug)]
+pub struct Reference {
+    pub name: Token,
+    /// Index of the declaration it refers to. `None` for built-ins and
+    /// undefined variables.
+    pub declaration: Option<usize>,
+}
+
+/// Where every variable of a program is declared and used, for editor
+/// tooling. Scoping follows the `Resolver`: locals are visible after their
+/// declaration, and anything not found in a local scope is a global.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+    /// Declarations of each local scope.
+    scopes: Vec<Vec<(Symbol, usize)>>,
+    /// Top-level declarations by name, collected before resolving.
+    globals: HashMap<Symbol, Vec<usize>>,
+    function: Option<usize>,
+}
+
+impl Analysis {
+    pub fn new(statements: &[Stmt]) -> Self {
+        let mut analysis = Self::default();
+        analysis.statements(statements);
+        analysis
+    }
+
+    /// The declaration or reference whose name is at `line` and `column`,
+    /// in the units of `Token`.
+    pub fn name_at(&self, line: u32, column: u32) -> Option<&Token> {
+        let covers = |name: &&Token| {
+            let length = name.lexeme.chars().count() as u32;
+            name.line == line && (name.column..=name.column + length).contains(&column)
+        };
+        self.declarations
+            .iter()
+            .map(|declaration| &declaration.name)
+            .chain(self.references.iter().map(|reference| &reference.name))
+            .find(covers)
+    }
+
+    /// The declaration that `name`, as returned by `name_at`, is or refers
+    /// to.
+    pub fn declaration_of(&self, name: &Token) -> Option<usize> {
+        let same = |other: &Token| other.line == name.line && other.column == name.column;
+        if let Some(index) = self.declarations.iter().position(|d| same(&d.name)) {
+            return Some(index);
+        }
+        self.references
+            .iter()
+            .find(|reference| same(&reference.name))?
+            .declaration
+    }
+
+    /// Every reference to the declaration at `index`.
+    pub fn references_to(&self, index: usize) -> impl Iterator<Item = &Reference> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.declaration == Some(index))
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        if self.scopes.is_empty() {
+            for statement in statements {
+                self.declare_global(statement);
+            }
+        }
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Records a top-level declaration before anything is resolved, as
+    /// globals can be used before they're declared.
+    fn declare_global(&mut self, stmt: &Stmt) {
+        let (name, kind, detail) = match stmt {
+            Stmt::Function(declaration) => match &declaration.name {
+                Some(name) => (name, DeclarationKind::Function, signature(declaration)),
+                None => return,
+            },
+            Stmt::Import(path, name) => (
+                name,
+                DeclarationKind::Module,
+                format!("import {} as {}", path.lexeme, name.lexeme),
+            ),
+            Stmt::Var(name, var_type, _) => (
+                name,
+                DeclarationKind::Variable,
+                format!("var {}", variable(name, var_type)),
+            ),
+            _ => return,
+        };
+        let index = self.push(name, kind, detail);
+        self.globals.entry(name.symbol).or_default().push(index);
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(statements) => self.block(statements),
+            Stmt::Break(_) | Stmt::Continue(_) => (),
+            Stmt::Expression(expr) | Stmt::Print(_, expr) | Stmt::Throw(_, expr) => {
+                self.expression(expr)
+            }
+            Stmt::Function(declaration) => {
+                let index = match &declaration.name {
+                    Some(name) => {
+                        self.declare(name, DeclarationKind::Function, || signature(declaration))
+                    }
+                    None => None,
+                };
+                self.function(declaration, index);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Import(path, name) => {
+                self.declare(name, DeclarationKind::Module, || {
+                    format!("import {} as {}", path.lexeme, name.lexeme)
+                });
+            }
+            Stmt::Return(_, value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.block(body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(vec![]);
+                    self.declare(name, DeclarationKind::Variable, || {
+                        format!("catch ({})", name.lexeme)
+                    });
+                    self.statements(handler);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+            Stmt::Var(name, var_type, initializer) => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name, DeclarationKind::Variable, || {
+                    format!("var {}", variable(name, var_type))
+                });
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(body);
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(vec![]);
+        self.statements(statements);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(name, value, _) => {
+                self.expression(value);
+                self.reference(name);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Compound { target, value, .. } => {
+                self.expression(target);
+                self.expression(value);
+            }
+            Expr::Get(object, _) => self.expression(object),
+            Expr::Grouping(expr) => self.expression(expr),
+            Expr::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+            }
+            Expr::Lambda(declaration) => self.function(declaration, None),
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expr::Literal(_) => (),
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expr::Set(object, _, value) => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::Variable(name, _) => self.reference(name),
+        }
+    }
+
+    /// `index` is the function's own declaration, which encloses the
+    /// declarations inside it.
+    fn function(&mut self, declaration: &FunctionDecl, index: Option<usize>) {
+        let function = self.function;
+        self.function = index.or(function);
+        self.scopes.push(vec![]);
+        for (param, param_type) in declaration.params.iter().zip(&declaration.param_types) {
+            self.declare(param, DeclarationKind::Parameter, || {
+                variable(param, param_type)
+            });
+        }
+        self.statements(&declaration.body);
+        self.scopes.pop();
+        self.function = function;
+    }
+
+    /// Declares `name` in the innermost scope. Globals were already
+    /// declared by `declare_global`, so their existing index is returned.
+    fn declare(
+        &mut self,
+        name: &Token,
+        kind: DeclarationKind,
+        detail: impl FnOnce() -> String,
+    ) -> Option<usize> {
+        if self.scopes.is_empty() {
+            return self
+                .declarations
+                .iter()
+                .position(|d| d.name.line == name.line && d.name.column == name.column);
+        }
+        let index = self.push(name, kind, detail());
+        self.scopes.last_mut()?.push((name.symbol, index));
+        Some(index)
+    }
+
+    fn push(&mut self, name: &Token, kind: DeclarationKind, detail: String) -> usize {
+        self.declarations.push(Declaration {
+            name: name.clone(),
+            kind,
+            detail,
+            parent: self.function,
+        });
+        self.declarations.len() - 1
+    }
+
+    fn reference(&mut self, name: &Token) {
+        let local = self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .iter()
+                .rev()
+                .find(|(symbol, _)| *symbol == name.symbol)
+                .map(|(_, index)| *index)
+        });
+        // A global declared more than once is the last declaration before
+        // the use, or the first one if they all come after it.
+        let global = || {
+            let candidates = self.globals.get(&name.symbol)?;
+            candidates
+                .iter()
+                .rev()
+                .find(|&&index| {
+                    let declared = &self.declarations[index].name;
+                    (declared.line, declared.column) < (name.line, name.column)
+                })
+                .or(candidates.first())
+                .copied()
+        };
+        let declaration = local.or_else(global);
+        self.references.push(Reference {
+            name: name.clone(),
+            declaration,
+        });
+    }
+}
+
+fn signature(declaration: &FunctionDecl) -> String {
+    let params: Vec<_> = declaration
+        .params
+        .iter()
+        .zip(&declaration.param_types)
+        .map(|(param, param_type)| variable(param, param_type))
+        .collect();
+    let name = declaration
+        .name
+        .as_ref()
+        .map_or(String::new(), |name| format!(" {}", name.lexeme));
+    let return_type = declaration
+        .return_type
+        .as_ref()
+        .map_or(String::new(), |t| format!(": {}", t.lexeme));
+    format!("fun{name}({}){return_type}", params.join(", "))
+}
+
+/// A name with its type annotation, if it has one.
+fn variable(name: &Token, annotation: &Option<Token>) -> String {
+    match annotation {
+        Some(annotation) => format!("{}: {}", name.lexeme, annotation.lexeme),
+        None => name.lexeme.clone(),
+    }
+}
@@ -0,0 +1,10 @@
+//! The Lox debug adapter. Editors start it and talk to it over standard
+//! input and output.
+
+use std::io;
+
+use crafting_interpreters::dap;
+
+fn main() -> io::Result<()> {
+    dap::serve(io::stdin().lock(), io::stdout())
+}
@@ -0,0 +1,15 @@
+//! The Lox language server. Editors start it and talk to it over standard
+//! input and output.
+
+use std::error::Error;
+
+use crafting_interpreters::lsp;
+use lsp_server::Connection;
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (connection, io_threads) = Connection::stdio();
+    lsp::serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
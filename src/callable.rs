@@ -0,0 +1,97 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    environment::Environment,
+    interpreter::{Interpreter, RuntimeError},
+    stmt::FunctionDecl,
+    token::{LiteralVal, Token},
+    Lox,
+};
+
+/// Signature shared by every built-in function. `paren` is the closing
+/// parenthesis of the call and is used to locate errors.
+pub type NativeFn = fn(
+    &mut Interpreter,
+    &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError>;
+
+/// A function implemented in Rust and exposed to Lox code as a global.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    /// Smallest and largest accepted argument count.
+    pub arity: (usize, usize),
+    pub func: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new(name: &'static str, arity: (usize, usize), func: NativeFn) -> Self {
+        Self { name, arity, func }
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// A function written in Lox, either declared with `fun name(...)` or
+/// created by a lambda expression. Both capture the environment they were
+/// evaluated in.
+#[derive(Clone)]
+pub struct LoxFunction {
+    pub declaration: Rc<FunctionDecl>,
+    pub closure: Rc<RefCell<Environment>>,
+}
+
+impl LoxFunction {
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.declaration.name {
+            Some(name) => write!(f, "<fn {}>", name.lexeme),
+            None => write!(f, "<fn>"),
+        }
+    }
+}
+
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.declaration, &other.declaration)
+            && Rc::ptr_eq(&self.closure, &other.closure)
+    }
+}
+
+/// Fails unless `count` arguments fit the inclusive `(min, max)` range.
+pub fn check_arity(
+    paren: &Token,
+    (min, max): (usize, usize),
+    count: usize,
+) -> Result<(), RuntimeError> {
+    if (min..=max).contains(&count) {
+        return Ok(());
+    }
+
+    let expected = if min == max {
+        min.to_string()
+    } else {
+        format!("{min} to {max}")
+    };
+    Err(RuntimeError::new(
+        Some(paren.clone()),
+        format!("Expected {expected} arguments but got {count}."),
+    ))
+}
@@ -0,0 +1,382 @@
+//! A Debug Adapter Protocol server. The program runs in the adapter's own
+//! process, driven by a `Debugger` whose frontend answers the client's
+//! requests while the program is stopped.
+//!
+//! Only one program, on one thread, is debugged per session: it's launched
+//! once the client has sent both `launch` and `configurationDone`.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    debugger::{describe, Debugger, Frontend, Paused, Reason, Step},
+    environment::Environment,
+    interpreter::Interpreter,
+    sandbox::Capabilities,
+    Lox,
+};
+
+const THREAD: i64 = 1;
+
+/// Serves one debugging session over `input` and `output`, until the
+/// client disconnects or closes `input`.
+pub fn serve(input: impl BufRead + 'static, output: impl Write + 'static) -> io::Result<()> {
+    let transport = Rc::new(RefCell::new(Transport {
+        input: Box::new(input),
+        output: Box::new(output),
+        seq: 0,
+    }));
+    let mut breakpoints = BTreeSet::new();
+    let mut launch = None;
+    let mut configured = false;
+
+    while launch.is_none() || !configured {
+        let Some(request) = transport.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let arguments = &request["arguments"];
+        let body = match command(&request) {
+            "initialize" => {
+                let capabilities = json!({ "supportsConfigurationDoneRequest": true });
+                transport.borrow_mut().respond(&request, Ok(capabilities))?;
+                transport.borrow_mut().event("initialized", json!({}))?;
+                continue;
+            }
+            "setBreakpoints" => Ok(set_breakpoints(&mut breakpoints, arguments)),
+            "launch" => {
+                let program = PathBuf::from(arguments["program"].as_str().unwrap_or_default());
+                if program.is_file() {
+                    let stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                    launch = Some((program, stop_on_entry));
+                    Ok(json!({}))
+                } else {
+                    Err(format!("Could not open '{}'.", program.display()))
+                }
+            }
+            "configurationDone" => {
+                configured = true;
+                Ok(json!({}))
+            }
+            "threads" => Ok(threads()),
+            "disconnect" => {
+                transport.borrow_mut().respond(&request, Ok(json!({})))?;
+                return Ok(());
+            }
+            command => Err(format!("Unsupported request '{command}'.")),
+        };
+        transport.borrow_mut().respond(&request, body)?;
+    }
+
+    let (program, stop_on_entry) = launch.unwrap();
+    let mut debugger = Debugger::new(Adapter {
+        transport: Rc::clone(&transport),
+        program: program.clone(),
+        scopes: vec![],
+    });
+    debugger.breakpoints = breakpoints;
+    if stop_on_entry {
+        debugger = debugger.stop_on_entry();
+    }
+
+    let mut lox = Lox::default();
+    lox.set_output(Output::new(&transport, "stdout"));
+    lox.set_diagnostics(Output::new(&transport, "stderr"));
+    let mut interpreter = Interpreter::new();
+    interpreter.capabilities = Capabilities::ALL;
+    interpreter.debugger = Some(debugger);
+    let environment = Rc::new(RefCell::new(Environment::global()));
+    lox.run_file(&program, &mut interpreter, &environment);
+    let exit_code = lox.exit_code();
+    // Sends the rest of the output.
+    drop(lox);
+
+    let mut transport = transport.borrow_mut();
+    transport.event("exited", json!({ "exitCode": exit_code }))?;
+    transport.event("terminated", json!({}))?;
+    while let Some(request) = transport.read()? {
+        match command(&request) {
+            "disconnect" => return transport.respond(&request, Ok(json!({}))),
+            "threads" => transport.respond(&request, Ok(threads()))?,
+            command => {
+                let error = format!("The program has ended, can't handle '{command}'.");
+                transport.respond(&request, Err(error))?
+            }
+        }
+    }
+    Ok(())
+}
+
+fn command(request: &Value) -> &str {
+    request["command"].as_str().unwrap_or_default()
+}
+
+fn threads() -> Value {
+    json!({ "threads": [{ "id": THREAD, "name": "main" }] })
+}
+
+/// Replaces `breakpoints` with the lines in a `setBreakpoints` request.
+fn set_breakpoints(breakpoints: &mut BTreeSet<u32>, arguments: &Value) -> Value {
+    let lines: Vec<u32> = match arguments["breakpoints"].as_array() {
+        Some(requested) => requested
+            .iter()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as u32)
+            .collect(),
+        None => vec![],
+    };
+    *breakpoints = lines.iter().copied().collect();
+    let verified: Vec<_> = lines
+        .iter()
+        .map(|line| json!({ "verified": true, "line": line }))
+        .collect();
+    json!({ "breakpoints": verified })
+}
+
+/// Messages framed with a `Content-Length` header, as in the Language
+/// Server Protocol.
+struct Transport {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: i64,
+}
+
+impl Transport {
+    /// The next message, or `None` once the input is closed.
+    fn read(&mut self) -> io::Result<Option<Value>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let length = length.ok_or_else(|| invalid("Missing Content-Length header."))?;
+        let mut content = vec![0; length];
+        self.input.read_exact(&mut content)?;
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|error| invalid(&error.to_string()))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let content = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{content}",
+            content.len()
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Sends what the program writes as `output` events, a line at a time.
+struct Output {
+    transport: Rc<RefCell<Transport>>,
+    category: &'static str,
+    line: Vec<u8>,
+}
+
+impl Output {
+    fn new(transport: &Rc<RefCell<Transport>>, category: &'static str) -> Self {
+        Self {
+            transport: Rc::clone(transport),
+            category,
+            line: vec![],
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if self.line.ends_with(b"\n") {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.line.is_empty() {
+            return Ok(());
+        }
+        let output = String::from_utf8_lossy(&std::mem::take(&mut self.line)).into_owned();
+        let body = json!({ "category": self.category, "output": output });
+        self.transport.borrow_mut().event("output", body)
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Answers requests about the stopped program until the client resumes
+/// it.
+struct Adapter {
+    transport: Rc<RefCell<Transport>>,
+    program: PathBuf,
+    /// Scopes handed out since the program stopped. A scope's variables
+    /// reference is its index plus one.
+    scopes: Vec<Rc<RefCell<Environment>>>,
+}
+
+impl Adapter {
+    fn request(&mut self, paused: &mut Paused, request: &Value) -> Result<Value, String> {
+        let arguments = &request["arguments"];
+        let frame = arguments["frameId"].as_u64().unwrap_or(0) as usize;
+        match command(request) {
+            "threads" => Ok(threads()),
+            "stackTrace" => {
+                let source = json!({ "path": self.program });
+                let frames: Vec<_> = paused
+                    .frames()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        json!({
+                            "id": id,
+                            "name": frame.name.to_string(),
+                            "line": frame.line,
+                            "column": 1,
+                            "source": source,
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "totalFrames": frames.len(), "stackFrames": frames }))
+            }
+            "scopes" => {
+                let scopes = paused.scopes(frame);
+                let globals = scopes.len().saturating_sub(1);
+                let scopes: Vec<_> = scopes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(depth, scope)| {
+                        let name = match depth {
+                            0 if depth < globals => "Locals".to_string(),
+                            _ if depth == globals => "Globals".to_string(),
+                            _ => format!("Enclosing {depth}"),
+                        };
+                        self.scopes.push(scope);
+                        json!({
+                            "name": name,
+                            "variablesReference": self.scopes.len(),
+                            "expensive": false,
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "scopes": scopes }))
+            }
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+                let scope = reference
+                    .checked_sub(1)
+                    .and_then(|index| self.scopes.get(index))
+                    .ok_or("Unknown variables reference.")?;
+                let variables: Vec<_> = scope
+                    .borrow()
+                    .variables()
+                    .into_iter()
+                    .map(|(name, value)| {
+                        json!({
+                            "name": name.to_string(),
+                            "value": describe(&value),
+                            "variablesReference": 0,
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "variables": variables }))
+            }
+            "evaluate" => {
+                let expression = arguments["expression"].as_str().unwrap_or_default();
+                let value = paused.evaluate(frame, expression)?;
+                Ok(json!({ "result": describe(&value), "variablesReference": 0 }))
+            }
+            "setBreakpoints" => Ok(set_breakpoints(paused.breakpoints, arguments)),
+            command => Err(format!("Unsupported request '{command}'.")),
+        }
+    }
+}
+
+impl Frontend for Adapter {
+    fn stopped(&mut self, paused: &mut Paused) -> Step {
+        self.scopes.clear();
+        let reason = match paused.reason {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+        };
+        let stopped = json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true });
+        if self
+            .transport
+            .borrow_mut()
+            .event("stopped", stopped)
+            .is_err()
+        {
+            return Step::Stop;
+        }
+
+        loop {
+            let request = match self.transport.borrow_mut().read() {
+                Ok(Some(request)) => request,
+                _ => return Step::Stop,
+            };
+            let step = match command(&request) {
+                "continue" => Some(Step::Continue),
+                "next" => Some(Step::Over),
+                "stepIn" => Some(Step::In),
+                "stepOut" => Some(Step::Out),
+                "disconnect" | "terminate" => Some(Step::Stop),
+                _ => None,
+            };
+            let body = match step {
+                Some(Step::Continue) => Ok(json!({ "allThreadsContinued": true })),
+                Some(_) => Ok(json!({})),
+                None => self.request(paused, &request),
+            };
+            if self.transport.borrow_mut().respond(&request, body).is_err() {
+                return Step::Stop;
+            }
+            if let Some(step) = step {
+                return step;
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    environment::Environment,
+    interpreter::{Frame, Interpreter},
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    stmt::Stmt,
+    token::LiteralVal,
+    Lox,
+};
+
+/// How to carry on after stopping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Stop at the next line, inside calls too.
+    In,
+    /// Stop at the next line of the same function or a caller.
+    Over,
+    /// Stop once the current function has returned.
+    Out,
+    /// Run to the end without stopping again.
+    Detach,
+    /// End the program with a runtime error.
+    Stop,
+}
+
+/// Why the program stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// What a debugger shows the user, and where it gets told what to do next.
+pub trait Frontend {
+    /// Called whenever the program stops. Returns when it should resume.
+    fn stopped(&mut self, paused: &mut Paused) -> Step;
+}
+
+/// Decides before every statement whether the program stops there, and
+/// hands the paused program to its frontend when it does.
+///
+/// Stops happen on lines: a statement on the same line as the previous one
+/// only counts as a new step, or hits a breakpoint again, if it's the same
+/// statement running again, as in a loop. Breakpoints are lines of the main
+/// script, not of imported modules.
+pub struct Debugger {
+    pub breakpoints: BTreeSet<u32>,
+    frontend: Box<dyn Frontend>,
+    /// How the program was resumed, and the stop it was resumed from.
+    step: Step,
+    from: Position,
+    /// The latest statement.
+    last: Position,
+    entry: bool,
+}
+
+/// Where a statement runs.
+#[derive(Clone, Copy, PartialEq)]
+struct Position {
+    line: u32,
+    /// Number of frames.
+    depth: usize,
+    /// Only compared, to tell a statement running again from another one.
+    statement: *const Stmt,
+}
+
+impl Position {
+    const START: Position = Position {
+        line: 0,
+        depth: 0,
+        statement: std::ptr::null(),
+    };
+
+    /// Whether running `self` after `other` makes for a new stop, if they
+    /// are at the same depth.
+    fn moved_from(&self, other: &Position) -> bool {
+        self.line != other.line || self.statement == other.statement
+    }
+}
+
+impl Debugger {
+    pub fn new(frontend: impl Frontend + 'static) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            frontend: Box::new(frontend),
+            step: Step::Continue,
+            from: Position::START,
+            last: Position::START,
+            entry: false,
+        }
+    }
+
+    /// Stops before the first statement, as well as at breakpoints.
+    pub fn stop_on_entry(mut self) -> Self {
+        self.entry = true;
+        self
+    }
+
+    /// Called before `stmt`, which is on `line`, runs. Returns how the
+    /// program was resumed, or `Step::Continue` if it didn't stop.
+    pub(crate) fn statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        lox: &mut Lox,
+        stmt: &Stmt,
+        line: u32,
+    ) -> Step {
+        let here = Position {
+            line,
+            depth: interpreter.frames.len(),
+            statement: stmt,
+        };
+        let reason = self.reason(here, interpreter.modules.importing());
+        self.last = here;
+        let Some(reason) = reason else {
+            return Step::Continue;
+        };
+
+        self.entry = false;
+        let mut paused = Paused {
+            interpreter,
+            lox,
+            breakpoints: &mut self.breakpoints,
+            reason,
+            line,
+        };
+        self.step = self.frontend.stopped(&mut paused);
+        self.from = here;
+        self.step
+    }
+
+    fn reason(&self, here: Position, importing: bool) -> Option<Reason> {
+        if self.entry {
+            return Some(Reason::Entry);
+        }
+        let from = self.from;
+        let stepped = match self.step {
+            Step::In => here.depth != from.depth || here.moved_from(&from),
+            Step::Over => {
+                here.depth < from.depth || (here.depth == from.depth && here.moved_from(&from))
+            }
+            Step::Out => here.depth < from.depth,
+            Step::Continue | Step::Detach | Step::Stop => false,
+        };
+        if stepped {
+            return Some(Reason::Step);
+        }
+        let breakpoint = !importing
+            && self.breakpoints.contains(&here.line)
+            && (here.depth != self.last.depth || here.moved_from(&self.last));
+        breakpoint.then_some(Reason::Breakpoint)
+    }
+}
+
+/// A stopped program, for frontends to inspect.
+pub struct Paused<'a> {
+    interpreter: &'a mut Interpreter,
+    lox: &'a mut Lox,
+    /// Changes take effect when the program resumes.
+    pub breakpoints: &'a mut BTreeSet<u32>,
+    pub reason: Reason,
+    pub line: u32,
+}
+
+impl Paused<'_> {
+    /// The calls in progress, innermost first. Frames are numbered in this
+    /// order by the other methods.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.interpreter.frames.iter().rev()
+    }
+
+    /// The scopes visible from `frame`, innermost first, so the last one
+    /// holds the globals.
+    pub fn scopes(&self, frame: usize) -> Vec<Rc<RefCell<Environment>>> {
+        let Some(frame) = self.frames().nth(frame) else {
+            return vec![];
+        };
+        let mut scopes = vec![Rc::clone(&frame.environment)];
+        loop {
+            let enclosing = scopes.last().unwrap().borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) => scopes.push(enclosing),
+                None => return scopes,
+            }
+        }
+    }
+
+    /// Evaluates the expression `source` as if it were written in the
+    /// statement `frame` is stopped at. Locals can be read but assigning
+    /// them only lasts for the evaluation; globals can be assigned.
+    pub fn evaluate(&mut self, frame: usize, source: &str) -> Result<LiteralVal, String> {
+        let mut scopes = self.scopes(frame);
+        let globals = scopes.pop().ok_or("No such frame.")?;
+        // Declaring the visible locals, outermost first, lets the resolver
+        // find them in the scope the expression is evaluated in.
+        let locals: Vec<_> = scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.borrow().variables())
+            .collect();
+        let declarations: String = locals
+            .iter()
+            .map(|(name, _)| format!("var {name};"))
+            .collect();
+
+        let mut lox = Lox::default();
+        lox.set_diagnostics(std::io::sink());
+        let tokens = Scanner::new(format!("{{{declarations}\n{source};}}")).scan_tokens(&mut lox);
+        let statements = Parser::new(tokens).parse(&mut lox);
+        if let Some(error) = lox.reported().first() {
+            return Err(error.message.clone());
+        }
+        let expr = match statements.as_slice() {
+            [Stmt::Block(block)] if block.len() == locals.len() + 1 => match block.last() {
+                Some(Stmt::Expression(expr)) => expr,
+                _ => return Err("Expect an expression.".to_string()),
+            },
+            _ => return Err("Expect a single expression.".to_string()),
+        };
+        Resolver::new().resolve(&statements);
+
+        let mut environment = Environment::with_enclosing(globals);
+        for (name, value) in locals {
+            environment.define(name, value);
+        }
+        let environment = Rc::new(RefCell::new(environment));
+        self.interpreter
+            .evaluate(self.lox, &environment, expr)
+            .map_err(|error| error.message)
+    }
+}
+
+/// How a value is shown in a debugger: like `print` does, with strings
+/// quoted.
+pub fn describe(value: &LiteralVal) -> String {
+    match value {
+        LiteralVal::UnInit => "<uninitialized>".to_string(),
+        _ => value.repr().unwrap_or_else(|error| error.message),
+    }
+}
+
+const HELP: &str = "\
+step, s          run to the next line, entering calls
+next, n          run to the next line in this function
+out, o           run until this function returns
+continue, c      run to the next breakpoint
+break, b LINE    stop at LINE
+delete, d LINE   remove the breakpoint at LINE
+backtrace, bt    show the calls in progress
+vars, v [FRAME]  show the variables visible in a frame, 0 by default
+print, p EXPR    evaluate EXPR in the current frame
+list, l          show the source around the current line
+quit, q          end the program";
+
+/// A line-based frontend for terminals, used by `--debug`. Shows where the
+/// program stopped and reads commands until one resumes it. The end of the
+/// input detaches the debugger.
+pub struct Console<R, W> {
+    input: R,
+    output: W,
+    source: Vec<String>,
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(source: &str, input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            source: source.lines().map(str::to_string).collect(),
+        }
+    }
+
+    fn source_line(&self, line: u32) -> &str {
+        self.source
+            .get(line as usize - 1)
+            .map_or("", |text| text.trim())
+    }
+
+    fn command(&mut self, paused: &mut Paused, command: &str, argument: &str) -> Option<Step> {
+        let out = &mut self.output;
+        match command {
+            "step" | "s" => return Some(Step::In),
+            "next" | "n" => return Some(Step::Over),
+            "out" | "o" => return Some(Step::Out),
+            "continue" | "c" => return Some(Step::Continue),
+            "quit" | "q" => return Some(Step::Stop),
+            "break" | "b" | "delete" | "d" => match argument.parse::<u32>() {
+                Ok(line) if command.starts_with('b') => {
+                    paused.breakpoints.insert(line);
+                    writeln!(out, "Breakpoint at line {line}.")
+                }
+                Ok(line) if paused.breakpoints.remove(&line) => {
+                    writeln!(out, "Deleted the breakpoint at line {line}.")
+                }
+                Ok(line) => writeln!(out, "No breakpoint at line {line}."),
+                Err(_) => writeln!(out, "Expect a line number."),
+            },
+            "backtrace" | "bt" => {
+                for (index, frame) in paused.frames().enumerate() {
+                    writeln!(out, "#{index} {} at line {}", frame.name, frame.line).unwrap();
+                }
+                Ok(())
+            }
+            "vars" | "v" => {
+                let frame = argument.parse().unwrap_or(0);
+                let scopes = paused.scopes(frame);
+                if scopes.is_empty() {
+                    writeln!(out, "No frame {frame}.").unwrap();
+                }
+                for (depth, scope) in scopes.iter().enumerate() {
+                    let label = if depth + 1 == scopes.len() {
+                        "globals".to_string()
+                    } else {
+                        format!("scope {depth}")
+                    };
+                    writeln!(out, "{label}:").unwrap();
+                    for (name, value) in scope.borrow().variables() {
+                        writeln!(out, "  {name} = {}", describe(&value)).unwrap();
+                    }
+                }
+                Ok(())
+            }
+            "print" | "p" => match paused.evaluate(0, argument) {
+                Ok(value) => writeln!(out, "{}", describe(&value)),
+                Err(message) => writeln!(out, "Error: {message}"),
+            },
+            "list" | "l" => {
+                let first = paused.line.saturating_sub(2).max(1);
+                let last = (paused.line + 2).min(self.source.len() as u32);
+                for line in first..=last {
+                    let marker = if line == paused.line { '>' } else { ' ' };
+                    let text = &self.source[line as usize - 1];
+                    writeln!(self.output, "{marker}{line:4} {text}").unwrap();
+                }
+                Ok(())
+            }
+            "help" | "h" => writeln!(out, "{HELP}"),
+            _ => writeln!(out, "Unknown command '{command}', try 'help'."),
+        }
+        .unwrap();
+        None
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Console<R, W> {
+    fn stopped(&mut self, paused: &mut Paused) -> Step {
+        let reason = match paused.reason {
+            Reason::Entry | Reason::Step => "Stopped",
+            Reason::Breakpoint => "Breakpoint",
+        };
+        let text = self.source_line(paused.line).to_string();
+        writeln!(self.output, "{reason} at line {}: {text}", paused.line).unwrap();
+        loop {
+            write!(self.output, "(debug) ").unwrap();
+            self.output.flush().unwrap();
+            let mut line = String::new();
+            if self.input.read_line(&mut line).unwrap_or(0) == 0 {
+                return Step::Detach;
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            if let Some(step) = self.command(paused, command, argument.trim()) {
+                return step;
+            }
+        }
+    }
+}
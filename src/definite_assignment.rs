@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    expr::Expr,
+    stmt::{FunctionDecl, Stmt},
+    token::{LiteralVal, Token},
+    Lox,
+};
+
+/// Variables that are definitely assigned at some point of the program.
+/// Code after `break`, `continue`, `return` or `throw` is unreachable and counts as
+/// having everything assigned, so it never weakens a join.
+#[derive(Clone)]
+struct State {
+    assigned: HashSet<usize>,
+    reachable: bool,
+}
+
+impl State {
+    fn unreachable() -> Self {
+        Self {
+            assigned: HashSet::new(),
+            reachable: false,
+        }
+    }
+
+    fn is_assigned(&self, variable: usize) -> bool {
+        !self.reachable || self.assigned.contains(&variable)
+    }
+
+    /// State where two control flow paths meet.
+    fn join(self, other: Self) -> Self {
+        match (self.reachable, other.reachable) {
+            (false, _) => other,
+            (_, false) => self,
+            _ => Self {
+                assigned: &self.assigned & &other.assigned,
+                reachable: true,
+            },
+        }
+    }
+}
+
+struct Variable {
+    declaration: Token,
+    /// Function nesting depth the variable was declared at.
+    function_depth: usize,
+}
+
+/// Static pass that rejects reading a `var` declared without an initializer
+/// before every path to the read has assigned it. Variables captured from
+/// an enclosing function can't be tracked, since the function may be called
+/// at any point; those are left to the check in `Environment::get`.
+pub struct DefiniteAssignment {
+    scopes: Vec<HashMap<String, usize>>,
+    variables: Vec<Variable>,
+    function_depth: usize,
+    state: State,
+    /// States at the `break`s of each enclosing loop, joined together.
+    breaks: Vec<State>,
+    /// First token of each run of statements that can't be reached, for
+    /// the linter to warn about.
+    unreachable: Vec<Token>,
+    /// Return types of the functions whose end can be reached, for the
+    /// type checker to check that they allow returning nothing.
+    falls_through: Vec<Token>,
+}
+
+impl Default for DefiniteAssignment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DefiniteAssignment {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            variables: vec![],
+            function_depth: 0,
+            state: State {
+                assigned: HashSet::new(),
+                reachable: true,
+            },
+            breaks: vec![],
+            unreachable: vec![],
+            falls_through: vec![],
+        }
+    }
+
+    pub fn check(&mut self, lox: &mut Lox, statements: &[Stmt]) {
+        for (i, statement) in statements.iter().enumerate() {
+            let reachable = self.state.reachable;
+            self.statement(lox, statement);
+            if reachable && !self.state.reachable {
+                // Literals have no token, so the run starts at the first
+                // statement that does.
+                if let Some(token) = statements[i + 1..].iter().find_map(Stmt::token) {
+                    self.unreachable.push(token.clone());
+                }
+            }
+        }
+    }
+
+    /// Where code that can't be reached starts, once `check` is done.
+    pub fn unreachable(&self) -> &[Token] {
+        &self.unreachable
+    }
+
+    /// Return type annotations of functions that can finish without a
+    /// `return`, once `check` is done.
+    pub fn falls_through(&self) -> &[Token] {
+        &self.falls_through
+    }
+
+    fn statement(&mut self, lox: &mut Lox, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(statements) => self.block(lox, statements),
+            Stmt::Break(_) => {
+                let state = std::mem::replace(&mut self.state, State::unreachable());
+                if let Some(breaks) = self.breaks.pop() {
+                    self.breaks.push(breaks.join(state));
+                }
+            }
+            Stmt::Continue(_) => self.state = State::unreachable(),
+            Stmt::Expression(expr) | Stmt::Print(_, expr) => self.expression(lox, expr),
+            Stmt::Function(declaration) => {
+                if let Some(name) = &declaration.name {
+                    self.declare(name, true);
+                }
+                self.function(lox, declaration);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(lox, condition);
+                let before = self.state.clone();
+                self.statement(lox, then_branch);
+                let after_then = std::mem::replace(&mut self.state, before);
+                if let Some(else_branch) = else_branch {
+                    self.statement(lox, else_branch);
+                }
+                let after_else = std::mem::replace(&mut self.state, State::unreachable());
+                self.state = after_then.join(after_else);
+            }
+            Stmt::Import(_, name) => self.declare(name, true),
+            Stmt::Return(_, value) => {
+                if let Some(value) = value {
+                    self.expression(lox, value);
+                }
+                self.state = State::unreachable();
+            }
+            Stmt::Throw(_, value) => {
+                self.expression(lox, value);
+                self.state = State::unreachable();
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                // The body can fail after any of its statements, so the
+                // handler and `finally` only see the state at entry.
+                let entry = self.state.clone();
+                self.block(lox, body);
+                if let Some((name, handler)) = catch {
+                    let after_body = std::mem::replace(&mut self.state, entry.clone());
+                    self.scopes.push(HashMap::new());
+                    self.declare(name, true);
+                    self.check(lox, handler);
+                    self.scopes.pop();
+                    let after_handler = std::mem::replace(&mut self.state, State::unreachable());
+                    self.state = after_body.join(after_handler);
+                }
+                if let Some(finally) = finally {
+                    let finished = std::mem::replace(&mut self.state, entry);
+                    self.block(lox, finally);
+                    // Carrying on after the statement means it finished
+                    // normally, and then ran `finally` to the end.
+                    if self.state.reachable {
+                        self.state = State {
+                            assigned: &finished.assigned | &self.state.assigned,
+                            reachable: finished.reachable,
+                        };
+                    }
+                }
+            }
+            Stmt::Var(name, _, initializer) => {
+                if let Some(initializer) = initializer {
+                    self.expression(lox, initializer);
+                }
+                self.declare(name, initializer.is_some());
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.expression(lox, condition);
+                // Assignments only ever add to the state, so looping back to
+                // the condition can't make it stronger than it is on entry.
+                let exit = if matches!(condition, Expr::Literal(LiteralVal::Boolean(true))) {
+                    State::unreachable()
+                } else {
+                    self.state.clone()
+                };
+
+                self.breaks.push(State::unreachable());
+                self.statement(lox, body);
+                if let Some(increment) = increment {
+                    // `continue` still runs the increment, so it sees the
+                    // state at loop entry at worst.
+                    self.state = self.state.clone().join(exit.clone());
+                    self.expression(lox, increment);
+                }
+                let breaks = self.breaks.pop().unwrap();
+                self.state = exit.join(breaks);
+            }
+        }
+    }
+
+    fn block(&mut self, lox: &mut Lox, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.check(lox, statements);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, lox: &mut Lox, expr: &Expr) {
+        match expr {
+            Expr::Assign(name, value, _) => {
+                self.expression(lox, value);
+                self.assign(name);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expression(lox, left);
+                self.expression(lox, right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(lox, callee);
+                for argument in arguments {
+                    self.expression(lox, argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(lox, condition);
+                let before = self.state.clone();
+                self.expression(lox, then_branch);
+                let after_then = std::mem::replace(&mut self.state, before);
+                self.expression(lox, else_branch);
+                let after_else = std::mem::replace(&mut self.state, State::unreachable());
+                self.state = after_then.join(after_else);
+            }
+            Expr::Compound { target, value, .. } => {
+                self.expression(lox, target);
+                self.expression(lox, value);
+            }
+            Expr::Get(object, _) => self.expression(lox, object),
+            Expr::Grouping(expr) => self.expression(lox, expr),
+            Expr::Index { object, index, .. } => {
+                self.expression(lox, object);
+                self.expression(lox, index);
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(lox, object);
+                self.expression(lox, index);
+                self.expression(lox, value);
+            }
+            Expr::Lambda(declaration) => self.function(lox, declaration),
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(lox, element);
+                }
+            }
+            Expr::Literal(_) => (),
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.expression(lox, key);
+                    self.expression(lox, value);
+                }
+            }
+            Expr::Set(object, _, value) => {
+                self.expression(lox, object);
+                self.expression(lox, value);
+            }
+            Expr::Unary { right, .. } => self.expression(lox, right),
+            Expr::Variable(name, _) => self.read(lox, name),
+        }
+    }
+
+    /// Function bodies are checked on their own: the enclosing function's
+    /// state neither flows in nor is changed by them.
+    fn function(&mut self, lox: &mut Lox, declaration: &FunctionDecl) {
+        let state = std::mem::replace(
+            &mut self.state,
+            State {
+                assigned: HashSet::new(),
+                reachable: true,
+            },
+        );
+        let breaks = std::mem::take(&mut self.breaks);
+        self.function_depth += 1;
+        self.scopes.push(HashMap::new());
+
+        for param in &declaration.params {
+            self.declare(param, true);
+        }
+        self.check(lox, &declaration.body);
+        if self.state.reachable {
+            self.falls_through.extend(declaration.return_type.clone());
+        }
+
+        self.scopes.pop();
+        self.function_depth -= 1;
+        self.breaks = breaks;
+        self.state = state;
+    }
+
+    fn declare(&mut self, name: &Token, assigned: bool) {
+        let id = self.variables.len();
+        self.variables.push(Variable {
+            declaration: name.clone(),
+            function_depth: self.function_depth,
+        });
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), id);
+        if assigned {
+            self.state.assigned.insert(id);
+        }
+    }
+
+    /// The variable `name` refers to, if it was declared in the function that
+    /// is being checked.
+    fn lookup(&self, name: &Token) -> Option<usize> {
+        let id = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))?;
+        (self.variables[*id].function_depth == self.function_depth).then_some(*id)
+    }
+
+    fn read(&mut self, lox: &mut Lox, name: &Token) {
+        if let Some(id) = self.lookup(name) {
+            if !self.state.is_assigned(id) {
+                let declaration = &self.variables[id].declaration;
+                lox.error_token(
+                    name,
+                    &format!(
+                        "Variable '{}' may be used before assignment (declared on line {}).",
+                        name.lexeme, declaration.line
+                    ),
+                );
+                // Only report the first use.
+                self.state.assigned.insert(id);
+            }
+        }
+    }
+
+    fn assign(&mut self, name: &Token) {
+        if let Some(id) = self.lookup(name) {
+            self.state.assigned.insert(id);
+        }
+    }
+}
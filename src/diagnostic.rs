@@ -0,0 +1,13 @@
+use crate::{lint::Lint, token::Token};
+
+/// An error or warning reported before the program runs, kept by `Lox` for
+/// tools that need more than the printed text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: u32,
+    /// The token the diagnostic is about. Scanner errors don't have one.
+    pub token: Option<Token>,
+    pub message: String,
+    /// The lint that found a warning. Errors don't have one.
+    pub lint: Option<Lint>,
+}
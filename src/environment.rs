@@ -2,61 +2,157 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     interpreter::RuntimeError,
+    natives,
+    symbol::Symbol,
     token::{LiteralVal, Token},
 };
 
+/// Variables of one scope. The global scope, the one without an enclosing
+/// environment, looks variables up by name. Every other scope stores its
+/// variables in slots, in the order they are defined, and is addressed with
+/// the `(depth, index)` pairs computed by the `Resolver`.
 #[derive(Clone)]
 pub struct Environment {
     pub enclosing: Option<Rc<RefCell<Self>>>,
-    values: HashMap<String, LiteralVal>,
+    globals: HashMap<Symbol, LiteralVal>,
+    slots: Vec<LiteralVal>,
+    /// Name of each slot, for the debugger.
+    names: Vec<Symbol>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
             enclosing: None,
-            values: HashMap::new(),
+            globals: HashMap::new(),
+            slots: vec![],
+            names: vec![],
         }
     }
 
+    /// A top-level environment with the built-in functions already defined.
+    pub fn global() -> Self {
+        let mut environment = Self::new();
+        natives::define_all(&mut environment);
+        environment
+    }
+
     pub fn with_enclosing(enclosing: Rc<RefCell<Self>>) -> Self {
         Self {
             enclosing: Some(enclosing),
-            values: HashMap::new(),
+            globals: HashMap::new(),
+            slots: vec![],
+            names: vec![],
         }
     }
 
-    pub fn define(&mut self, name: String, value: LiteralVal) {
-        self.values.insert(name, value);
+    /// Globals are stored under `name`; locals take the next free slot.
+    pub fn define(&mut self, name: Symbol, value: LiteralVal) {
+        if self.enclosing.is_some() {
+            self.slots.push(value);
+            self.names.push(name);
+        } else {
+            self.globals.insert(name, value);
+        }
     }
 
+    /// Reads a global, from whatever scope the lookup starts in.
     pub fn get(&self, name: &Token) -> Result<LiteralVal, RuntimeError> {
-        if let Some(value) = self.values.get(&name.lexeme) {
-            Ok(value.clone())
-        } else {
-            if let Some(enclosing) = &self.enclosing {
-                return enclosing.borrow().get(name);
-            }
-
-            Err(RuntimeError(
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow().get(name);
+        }
+        match self.globals.get(&name.symbol) {
+            Some(value) => initialized(name, value),
+            None => Err(RuntimeError::new(
                 Some(name.clone()),
                 format!("Undefined variable '{}'.", &name.lexeme),
-            ))
+            )),
         }
     }
 
     pub fn assign(&mut self, name: &Token, value: LiteralVal) -> Result<(), RuntimeError> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.clone(), value);
-            Ok(())
-        } else {
-            if let Some(enclosing) = &mut self.enclosing {
-                return enclosing.borrow_mut().assign(name, value);
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow_mut().assign(name, value);
+        }
+        match self.globals.get_mut(&name.symbol) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
             }
-            Err(RuntimeError(
+            None => Err(RuntimeError::new(
                 Some(name.clone()),
                 format!("Undefined variable '{}'", &name.lexeme),
-            ))
+            )),
         }
     }
+
+    /// Reads the local `index` of the scope `depth` levels up.
+    pub fn get_at(
+        &self,
+        depth: usize,
+        index: usize,
+        name: &Token,
+    ) -> Result<LiteralVal, RuntimeError> {
+        match depth {
+            0 => match self.slots.get(index) {
+                Some(value) => initialized(name, value),
+                // Only a function declared further down the scope is
+                // resolved to a slot that isn't there yet.
+                None => Err(RuntimeError::new(
+                    Some(name.clone()),
+                    format!("Function '{}' is used before it is declared.", &name.lexeme),
+                )),
+            },
+            _ => self.ancestor().borrow().get_at(depth - 1, index, name),
+        }
+    }
+
+    pub fn assign_at(&mut self, depth: usize, index: usize, value: LiteralVal) {
+        match depth {
+            0 => self.slots[index] = value,
+            _ => self
+                .ancestor()
+                .borrow_mut()
+                .assign_at(depth - 1, index, value),
+        }
+    }
+
+    /// The variables of this scope, without those of enclosing ones, in
+    /// the order they were defined. Globals come sorted by name instead,
+    /// and without the built-in functions.
+    pub fn variables(&self) -> Vec<(Symbol, LiteralVal)> {
+        if self.enclosing.is_some() {
+            return self.names.iter().copied().zip(self.slots.clone()).collect();
+        }
+        let mut globals: Vec<_> = self
+            .globals
+            .iter()
+            .filter(|(_, value)| !matches!(value, LiteralVal::Native(_)))
+            .map(|(name, value)| (*name, value.clone()))
+            .collect();
+        globals.sort_by_key(|(name, _)| name.name());
+        globals
+    }
+
+    fn ancestor(&self) -> &Rc<RefCell<Self>> {
+        self.enclosing
+            .as_ref()
+            .expect("resolved depth is deeper than the scope chain")
+    }
+}
+
+fn initialized(name: &Token, value: &LiteralVal) -> Result<LiteralVal, RuntimeError> {
+    if matches!(value, LiteralVal::UnInit) {
+        return Err(RuntimeError::new(
+            Some(name.clone()),
+            format!("Variable '{}' is used before being assigned.", &name.lexeme),
+        ));
+    }
+    Ok(value.clone())
 }
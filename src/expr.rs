@@ -1,20 +1,98 @@
-use crate::token::{LiteralVal, Token};
+use std::{cell::Cell, rc::Rc};
 
-#[derive(Clone)]
+use crate::{
+    stmt::FunctionDecl,
+    token::{LiteralVal, Token},
+};
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Expr {
-    Assign(Token, Box<Expr>),
+    Assign(Token, Box<Expr>, Slot),
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    },
+    /// `target op= value`, `++target` and `target++`. The target is only
+    /// evaluated once. `operator` is the plain binary operator (`+` for
+    /// `+=`), and `postfix` makes the expression produce the old value.
+    Compound {
+        target: Box<Expr>,
+        operator: Token,
+        value: Box<Expr>,
+        postfix: bool,
+    },
+    Get(Box<Expr>, Token),
     Grouping(Box<Expr>),
+    Lambda(Rc<FunctionDecl>),
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    IndexSet {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    List(Vec<Expr>),
     Literal(LiteralVal),
+    /// Opening brace and the key/value pairs.
+    Map(Token, Vec<(Expr, Expr)>),
+    Set(Box<Expr>, Token, Box<Expr>),
     Unary {
         operator: Token,
         right: Box<Expr>,
     },
-    Variable(Token),
+    Variable(Token, Slot),
+}
+
+impl Expr {
+    /// Line of the expression's first token. Literals have no token, so
+    /// an expression made only of literals has no line.
+    pub fn line(&self) -> Option<u32> {
+        self.token().map(|token| token.line)
+    }
+
+    /// The expression's first token, skipping literals.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Expr::Assign(name, ..) | Expr::Variable(name, _) => Some(name),
+            Expr::Binary { left, operator, .. } => left.token().or(Some(operator)),
+            Expr::Call { callee, paren, .. } => callee.token().or(Some(paren)),
+            Expr::Conditional { condition, .. } => condition.token(),
+            Expr::Compound {
+                target, operator, ..
+            } => target.token().or(Some(operator)),
+            Expr::Get(object, name) | Expr::Set(object, name, _) => object.token().or(Some(name)),
+            Expr::Grouping(expr) => expr.token(),
+            Expr::Index {
+                object, bracket, ..
+            }
+            | Expr::IndexSet {
+                object, bracket, ..
+            } => object.token().or(Some(bracket)),
+            Expr::Lambda(declaration) => declaration
+                .params
+                .first()
+                .or_else(|| declaration.body.first()?.token()),
+            Expr::List(elements) => elements.iter().find_map(Expr::token),
+            Expr::Literal(_) => None,
+            Expr::Map(brace, _) => Some(brace),
+            Expr::Unary { operator, .. } => Some(operator),
+        }
+    }
 }
 
 // fn paranthesize(name: &str, exprs: &[&Expr]) -> String {
@@ -44,3 +122,19 @@ pub enum Expr {
 //         }
 //     }
 // }
+
+/// Where a variable lives, filled in by the `Resolver` before the program
+/// runs. Unresolved variables are globals and are looked up by name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Slot(Cell<Option<(usize, usize)>>);
+
+impl Slot {
+    /// Scopes to walk up and index of the variable in that scope.
+    pub fn get(&self) -> Option<(usize, usize)> {
+        self.0.get()
+    }
+
+    pub fn set(&self, depth: usize, index: usize) {
+        self.0.set(Some((depth, index)));
+    }
+}
@@ -1,42 +1,226 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    mem::size_of,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 type EnvRef<'a> = &'a Rc<RefCell<Environment>>;
 
 use crate::{
+    callable::{check_arity, LoxFunction},
+    debugger::{Debugger, Step},
+    definite_assignment::DefiniteAssignment,
     environment::Environment,
-    expr::Expr,
+    expr::{Expr, Slot},
+    lint::Linter,
+    map::{LoxMap, MapKey},
+    module::{Module, ModuleCache},
+    parser::Parser,
+    resolver::Resolver,
+    sandbox::{Capabilities, Limits},
+    scanner::Scanner,
     stmt::Stmt,
+    symbol::Symbol,
     token::{LiteralVal, Token, TokenType},
+    types::TypeChecker,
     Lox,
 };
 
 #[derive(Debug)]
-pub struct RuntimeError(pub Option<Token>, pub String);
+pub struct RuntimeError {
+    /// Where the error happened, if a token tells. Boxed to keep results
+    /// small.
+    pub token: Option<Box<Token>>,
+    pub message: String,
+    /// The calls the error unwound, innermost first.
+    pub trace: Vec<Call>,
+    /// The value of a `throw`, `None` for errors the interpreter raises.
+    pub thrown: Option<Box<LiteralVal>>,
+    /// Whether a `catch` can handle the error.
+    pub catchable: bool,
+}
+
+impl RuntimeError {
+    pub fn new(token: Option<Token>, message: String) -> Self {
+        Self {
+            token: token.map(Box::new),
+            message,
+            trace: vec![],
+            thrown: None,
+            catchable: true,
+        }
+    }
+
+    /// An error that ends the program however it's written: `catch` doesn't
+    /// handle it and `finally` blocks don't run.
+    pub fn fatal(token: Option<Token>, message: String) -> Self {
+        Self {
+            catchable: false,
+            ..Self::new(token, message)
+        }
+    }
+
+    /// A `throw` of `value`, which is only reported if nothing catches it.
+    pub fn throw(keyword: &Token, value: LiteralVal) -> Self {
+        let shown = value.print().unwrap_or_else(|error| error.message);
+        Self {
+            thrown: Some(Box::new(value)),
+            ..Self::new(
+                Some(keyword.clone()),
+                format!("Uncaught exception: {shown}"),
+            )
+        }
+    }
+
+    /// What a `catch` binds: the thrown value, or for the interpreter's own
+    /// errors a map of their `message` and `line`.
+    fn caught(self) -> LiteralVal {
+        if let Some(value) = self.thrown {
+            return *value;
+        }
+        let line = match self.token {
+            Some(token) => LiteralVal::Number(token.line as f32),
+            None => Nil,
+        };
+        let mut error = LoxMap::default();
+        error.insert(
+            MapKey::Str("message".to_string()),
+            LiteralVal::Str(self.message),
+        );
+        error.insert(MapKey::Str("line".to_string()), line);
+        LiteralVal::Map(Rc::new(RefCell::new(error)))
+    }
+}
+
+/// A call of a Lox function, in a `RuntimeError`'s trace.
+#[derive(Debug, PartialEq)]
+pub struct Call {
+    /// Name of the function, `<fn>` for lambdas.
+    pub function: Symbol,
+    /// Line of the call.
+    pub line: u32,
+}
+
+/// How a statement finished. Anything other than `Normal` unwinds the
+/// enclosing blocks until a loop handles it.
+#[derive(Debug, PartialEq)]
+pub enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(LiteralVal),
+}
 
 use LiteralVal::Nil;
 
-pub struct Interpreter {}
+/// How deep calls can nest before the program fails with a stack overflow,
+/// unless `Limits::frames` says otherwise.
+pub const MAX_FRAMES: usize = 1024;
+
+/// Sizes counted towards `Limits::allocation` for each list element and
+/// map entry. Strings count their length.
+pub(crate) const LIST_ELEMENT: usize = size_of::<LiteralVal>();
+pub(crate) const MAP_ENTRY: usize = size_of::<(MapKey, LiteralVal)>();
+
+/// Statements between two looks at the clock for `Limits::time`.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Calls get at least this much Rust stack. A call evaluates recursively, so
+/// this has to cover the deepest expression between two calls; the stack
+/// grows by `STACK_SEGMENT` whenever less is left. That way calls nest up to
+/// `Limits::frames` deep however small the thread's stack is.
+pub(crate) const STACK_RED_ZONE: usize = 256 * 1024;
+pub(crate) const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// A call in progress. The outermost frame is the script itself.
+pub struct Frame {
+    /// Name of the function, `<fn>` for lambdas, `<script>` for the script
+    /// and `<module name>` for imported modules.
+    pub name: Symbol,
+    /// Line of the statement running in this frame. In callers that's the
+    /// call. Statements only update it while a debugger is attached.
+    pub line: u32,
+    /// Innermost scope of that statement, also only kept up to date while
+    /// debugging.
+    pub environment: Rc<RefCell<Environment>>,
+}
+
+/// What the current run has used of its `Limits`.
+#[derive(Default)]
+struct Usage {
+    steps: u64,
+    allocated: usize,
+    deadline: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct Interpreter {
+    pub modules: ModuleCache,
+    /// Asked before every statement whether to stop there.
+    pub debugger: Option<Debugger>,
+    /// Side effects the program may have besides printing. None by default.
+    pub capabilities: Capabilities,
+    pub limits: Limits,
+    /// Setting this, from another thread or a signal handler, stops the
+    /// program before its next statement. It's cleared again once the
+    /// program has stopped.
+    pub cancel: Arc<AtomicBool>,
+    pub(crate) frames: Vec<Frame>,
+    usage: Usage,
+}
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// Starts counting towards `limits` from zero.
+    pub(crate) fn begin_run(&mut self) {
+        self.usage = Usage {
+            deadline: self.limits.time.map(|time| Instant::now() + time),
+            ..Usage::default()
+        };
     }
-    pub fn interpret(&mut self, lox: &mut Lox, statements: Vec<Stmt>, environment: EnvRef) {
+
+    pub fn interpret(&mut self, lox: &mut Lox, statements: &[Stmt], environment: EnvRef) {
+        self.begin_run();
+        self.frames.push(Frame {
+            name: Symbol::intern("<script>"),
+            line: 0,
+            environment: Rc::clone(environment),
+        });
         for statement in statements {
-            match self.execute(lox, environment, statement) {
-                Err(e) => {
-                    lox.runtime_error(e);
-                    break;
-                }
-                _ => (),
+            if let Err(e) = self.execute(lox, environment, statement) {
+                lox.runtime_error(e);
+                break;
             }
         }
+        self.frames.pop();
     }
+    /// Expressions can nest a couple of hundred levels deep, which in a
+    /// debug build takes more stack than a small thread has, so it grows as
+    /// needed, as it does for calls.
     pub fn evaluate(
         &mut self,
         lox: &mut Lox,
         environment: EnvRef,
         expr: &Expr,
+    ) -> Result<LiteralVal, RuntimeError> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            self.evaluate_expr(lox, environment, expr)
+        })
+    }
+
+    fn evaluate_expr(
+        &mut self,
+        lox: &mut Lox,
+        environment: EnvRef,
+        expr: &Expr,
     ) -> Result<LiteralVal, RuntimeError> {
         let mut res = match expr {
             Expr::Binary {
@@ -44,174 +228,834 @@ impl Interpreter {
                 operator,
                 right,
             } => {
-                let left = self.evaluate(lox, environment, left.as_ref());
-                let right = self.evaluate(lox, environment, right.as_ref());
-
-                match operator.token_type {
-                    TokenType::Greater => LiteralVal::Boolean(
-                        left?.number_operand(operator.clone())?
-                            > right?.number_operand(operator.clone())?,
-                    )
-                    .into(),
-                    TokenType::GreaterEqual => LiteralVal::Boolean(
-                        left?.number_operand(operator.clone())?
-                            >= right?.number_operand(operator.clone())?,
-                    )
-                    .into(),
-                    TokenType::Less => LiteralVal::Boolean(
-                        left?.number_operand(operator.clone())?
-                            < right?.number_operand(operator.clone())?,
-                    )
-                    .into(),
-                    TokenType::LessEqual => LiteralVal::Boolean(
-                        left?.number_operand(operator.clone())?
-                            <= right?.number_operand(operator.clone())?,
-                    )
-                    .into(),
-
-                    TokenType::BangEqual => {
-                        LiteralVal::Boolean(!self.is_equal(&left?, &right?)).into()
-                    }
+                let left = self.evaluate(lox, environment, left.as_ref())?;
+                let right = self.evaluate(lox, environment, right.as_ref())?;
+                self.operate(operator, left, right)
+            }
 
-                    TokenType::EqualEqual => {
-                        LiteralVal::Boolean(self.is_equal(&left?, &right?)).into()
-                    }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.evaluate(lox, environment, callee)?;
+                let mut args = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    args.push(self.evaluate(lox, environment, argument)?);
+                }
+                self.call(lox, callee, paren, args)
+            }
+
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(lox, environment, object)?;
+                let index = self.evaluate(lox, environment, index)?;
+                self.index_get(&object, &index, bracket)
+            }
+
+            Expr::IndexSet {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.evaluate(lox, environment, object)?;
+                let index = self.evaluate(lox, environment, index)?;
+                let value = self.evaluate(lox, environment, value)?;
+                self.index_set(&object, &index, value.clone(), bracket)?;
+                Ok(value)
+            }
+
+            Expr::Lambda(declaration) => Ok(LiteralVal::Function(LoxFunction {
+                declaration: Rc::clone(declaration),
+                closure: Rc::clone(environment),
+            })),
+
+            Expr::List(elements) => {
+                self.allocate(None, elements.len() * LIST_ELEMENT)?;
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.evaluate(lox, environment, element)?);
+                }
+                Ok(LiteralVal::List(Rc::new(RefCell::new(values))))
+            }
+
+            Expr::Map(brace, entries) => {
+                self.allocate(Some(brace), entries.len() * MAP_ENTRY)?;
+                let mut map = LoxMap::default();
+                for (key, value) in entries {
+                    let key = MapKey::new(&self.evaluate(lox, environment, key)?, brace)?;
+                    map.insert(key, self.evaluate(lox, environment, value)?);
+                }
+                Ok(LiteralVal::Map(Rc::new(RefCell::new(map))))
+            }
+
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.evaluate(lox, environment, condition)?;
+                if self.is_truthy(&condition) {
+                    self.evaluate(lox, environment, then_branch)
+                } else {
+                    self.evaluate(lox, environment, else_branch)
+                }
+            }
 
-                    TokenType::Minus => LiteralVal::Number(
-                        left?.number_operand(operator.clone())?
-                            - right?.number_operand(operator.clone())?,
-                    )
-                    .into(),
-                    TokenType::Slash => {
-                        let right_val = right?.number_operand(operator.clone())?;
-                        if right_val == 0. {
-                            Err(RuntimeError(
-                                Some(operator.clone()),
-                                "Division by zero.".to_string(),
-                            ))
-                        } else {
-                            LiteralVal::Number(left?.number_operand(operator.clone())? / right_val)
-                                .into()
-                        }
+            Expr::Compound {
+                target,
+                operator,
+                value,
+                postfix,
+            } => {
+                let (old, new) = match target.as_ref() {
+                    Expr::Variable(name, slot) => {
+                        let old = self.look_up(environment, name, slot)?;
+                        let new = self.compound(lox, environment, operator, &old, value)?;
+                        self.assign(environment, name, slot, new.clone())?;
+                        (old, new)
+                    }
+                    Expr::Get(object, name) => {
+                        let module = self.module_operand(lox, environment, object, name)?;
+                        let old = module.get(name)?;
+                        let new = self.compound(lox, environment, operator, &old, value)?;
+                        module.set(name, new.clone())?;
+                        (old, new)
+                    }
+                    Expr::Index {
+                        object,
+                        bracket,
+                        index,
+                    } => {
+                        let object = self.evaluate(lox, environment, object)?;
+                        let index = self.evaluate(lox, environment, index)?;
+                        let old = self.index_get(&object, &index, bracket)?;
+                        let new = self.compound(lox, environment, operator, &old, value)?;
+                        self.index_set(&object, &index, new.clone(), bracket)?;
+                        (old, new)
                     }
-                    TokenType::Star => LiteralVal::Number(
-                        left?.number_operand(operator.clone())?
-                            * right?.number_operand(operator.clone())?,
-                    )
-                    .into(),
-                    TokenType::Plus => match (left, right) {
-                        (Ok(LiteralVal::Number(nl)), Ok(LiteralVal::Number(nr))) => {
-                            Ok(LiteralVal::Number(nl + nr))
-                        }
-                        (Ok(LiteralVal::Str(sl)), Ok(LiteralVal::Str(sr))) => {
-                            Ok(LiteralVal::Str(sl + &sr))
-                        }
-                        _ => Err(RuntimeError(
-                            Some(operator.clone()),
-                            "Operands must be two numbers or two strings.".to_string(),
-                        )),
-                    },
                     _ => unreachable!(),
-                }
+                };
+                Ok(if *postfix { old } else { new })
             }
 
+            Expr::Set(object, name, value) => {
+                let module = self.module_operand(lox, environment, object, name)?;
+                let value = self.evaluate(lox, environment, value)?;
+                module.set(name, value.clone())?;
+                Ok(value)
+            }
+
+            Expr::Get(object, name) => self
+                .module_operand(lox, environment, object, name)?
+                .get(name),
+
             Expr::Grouping(expr) => self.evaluate(lox, environment, expr.as_ref()),
 
             Expr::Literal(value) => Ok(value.clone()),
 
             Expr::Unary { operator, right } => {
-                let right = self.evaluate(lox, environment, right.as_ref());
-
-                match operator.token_type {
-                    TokenType::Minus => {
-                        LiteralVal::Number(right?.number_operand(operator.clone())? * -1.).into()
-                    }
-
-                    TokenType::Bang => Ok(LiteralVal::Boolean(!self.is_truthy(&right?))),
-                    _ => unreachable!(),
-                }
+                let right = self.evaluate(lox, environment, right.as_ref())?;
+                self.unary(operator, right)
             }
 
-            Expr::Variable(name) => environment.borrow().get(name),
-            Expr::Assign(name, expr) => {
+            Expr::Variable(name, slot) => self.look_up(environment, name, slot),
+            Expr::Assign(name, expr, slot) => {
                 let value = self.evaluate(lox, environment, expr.as_ref())?;
-                environment.borrow_mut().assign(name, value.clone())?;
+                self.assign(environment, name, slot, value.clone())?;
                 Ok(value)
             }
         };
-        match res {
-            Ok(LiteralVal::Number(n)) => {
-                if n.is_nan() {
-                    res = Ok(LiteralVal::NaN)
-                }
+        if let Ok(LiteralVal::Number(n)) = res {
+            if n.is_nan() {
+                res = Ok(LiteralVal::NaN)
             }
-            _ => (),
         }
         res
     }
 
-    fn execute(
+    /// Applies a prefix operator to an already evaluated operand.
+    pub(crate) fn unary(
+        &self,
+        operator: &Token,
+        right: LiteralVal,
+    ) -> Result<LiteralVal, RuntimeError> {
+        match operator.token_type {
+            TokenType::Minus => LiteralVal::Number(-right.number_operand(operator)?).into(),
+            TokenType::Bang => Ok(LiteralVal::Boolean(!self.is_truthy(&right))),
+            TokenType::Tilde => LiteralVal::Number(!right.integer_operand(operator)? as f32).into(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Applies a binary operator to two already evaluated operands.
+    pub(crate) fn binary(
+        &self,
+        operator: &Token,
+        left: LiteralVal,
+        right: LiteralVal,
+    ) -> Result<LiteralVal, RuntimeError> {
+        match operator.token_type {
+            TokenType::Greater => LiteralVal::Boolean(
+                left.number_operand(operator)? > right.number_operand(operator)?,
+            )
+            .into(),
+            TokenType::GreaterEqual => LiteralVal::Boolean(
+                left.number_operand(operator)? >= right.number_operand(operator)?,
+            )
+            .into(),
+            TokenType::Less => LiteralVal::Boolean(
+                left.number_operand(operator)? < right.number_operand(operator)?,
+            )
+            .into(),
+            TokenType::LessEqual => LiteralVal::Boolean(
+                left.number_operand(operator)? <= right.number_operand(operator)?,
+            )
+            .into(),
+
+            TokenType::Comma => Ok(right),
+
+            TokenType::BangEqual => LiteralVal::Boolean(!self.is_equal(&left, &right)).into(),
+
+            TokenType::EqualEqual => LiteralVal::Boolean(self.is_equal(&left, &right)).into(),
+
+            TokenType::Minus => {
+                LiteralVal::Number(left.number_operand(operator)? - right.number_operand(operator)?)
+                    .into()
+            }
+            TokenType::Slash => {
+                let right_val = right.number_operand(operator)?;
+                if right_val == 0. {
+                    Err(RuntimeError::new(
+                        Some(operator.clone()),
+                        "Division by zero.".to_string(),
+                    ))
+                } else {
+                    LiteralVal::Number(left.number_operand(operator)? / right_val).into()
+                }
+            }
+            TokenType::Percent | TokenType::TildeSlash => {
+                let left = left.number_operand(operator)?;
+                let right = right.number_operand(operator)?;
+                if right == 0. {
+                    return Err(RuntimeError::new(
+                        Some(operator.clone()),
+                        "Division by zero.".to_string(),
+                    ));
+                }
+                // Both round towards negative infinity, so the remainder has
+                // the sign of the divisor and `(a ~/ b) * b + a % b == a`.
+                let quotient = (left / right).floor();
+                if operator.token_type == TokenType::TildeSlash {
+                    Ok(LiteralVal::Number(quotient))
+                } else {
+                    Ok(LiteralVal::Number(left - right * quotient))
+                }
+            }
+            TokenType::StarStar => LiteralVal::Number(
+                left.number_operand(operator)?
+                    .powf(right.number_operand(operator)?),
+            )
+            .into(),
+            TokenType::Ampersand => LiteralVal::Number(
+                (left.integer_operand(operator)? & right.integer_operand(operator)?) as f32,
+            )
+            .into(),
+            TokenType::Pipe => LiteralVal::Number(
+                (left.integer_operand(operator)? | right.integer_operand(operator)?) as f32,
+            )
+            .into(),
+            TokenType::Caret => LiteralVal::Number(
+                (left.integer_operand(operator)? ^ right.integer_operand(operator)?) as f32,
+            )
+            .into(),
+            TokenType::LessLess | TokenType::GreaterGreater => {
+                let left = left.integer_operand(operator)?;
+                let right = right.integer_operand(operator)?;
+                if !(0..32).contains(&right) {
+                    return Err(RuntimeError::new(
+                        Some(operator.clone()),
+                        "Shift amount must be between 0 and 31.".to_string(),
+                    ));
+                }
+                if operator.token_type == TokenType::LessLess {
+                    Ok(LiteralVal::Number((left << right) as f32))
+                } else {
+                    Ok(LiteralVal::Number((left >> right) as f32))
+                }
+            }
+            TokenType::Star => {
+                LiteralVal::Number(left.number_operand(operator)? * right.number_operand(operator)?)
+                    .into()
+            }
+            TokenType::Plus => match (left, right) {
+                (LiteralVal::Number(nl), LiteralVal::Number(nr)) => Ok(LiteralVal::Number(nl + nr)),
+                (LiteralVal::Str(sl), LiteralVal::Str(sr)) => Ok(LiteralVal::Str(sl + &sr)),
+                _ => Err(RuntimeError::new(
+                    Some(operator.clone()),
+                    "Operands must be two numbers or two strings.".to_string(),
+                )),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn look_up(
+        &self,
+        environment: EnvRef,
+        name: &Token,
+        slot: &Slot,
+    ) -> Result<LiteralVal, RuntimeError> {
+        match slot.get() {
+            Some((depth, index)) => environment.borrow().get_at(depth, index, name),
+            None => environment.borrow().get(name),
+        }
+    }
+
+    fn assign(
+        &self,
+        environment: EnvRef,
+        name: &Token,
+        slot: &Slot,
+        value: LiteralVal,
+    ) -> Result<(), RuntimeError> {
+        match slot.get() {
+            Some((depth, index)) => {
+                environment.borrow_mut().assign_at(depth, index, value);
+                Ok(())
+            }
+            None => environment.borrow_mut().assign(name, value),
+        }
+    }
+
+    /// Evaluates the right hand side of a compound assignment and combines it
+    /// with the target's current value.
+    fn compound(
+        &mut self,
+        lox: &mut Lox,
+        environment: EnvRef,
+        operator: &Token,
+        old: &LiteralVal,
+        value: &Expr,
+    ) -> Result<LiteralVal, RuntimeError> {
+        let value = self.evaluate(lox, environment, value)?;
+        self.operate(operator, old.clone(), value)
+    }
+
+    /// Applies a binary operator like `binary`, counting the strings `+`
+    /// makes towards the allocation limit.
+    fn operate(
+        &mut self,
+        operator: &Token,
+        left: LiteralVal,
+        right: LiteralVal,
+    ) -> Result<LiteralVal, RuntimeError> {
+        let value = self.binary(operator, left, right)?;
+        if let (TokenType::Plus, LiteralVal::Str(s)) = (&operator.token_type, &value) {
+            self.allocate(Some(operator), s.len())?;
+        }
+        Ok(value)
+    }
+
+    /// Counts `bytes` the program is about to create towards the allocation
+    /// limit, failing at `token` if that goes over it.
+    pub(crate) fn allocate(
+        &mut self,
+        token: Option<&Token>,
+        bytes: usize,
+    ) -> Result<(), RuntimeError> {
+        self.usage.allocated = self.usage.allocated.saturating_add(bytes);
+        match self.limits.allocation {
+            Some(max) if self.usage.allocated > max => Err(RuntimeError::fatal(
+                token.cloned(),
+                format!("Exceeded the allocation limit of {max} bytes."),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a statement towards the step limit and checks the time limit
+    /// and whether the program was cancelled.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        if self.cancel.load(Ordering::Relaxed) {
+            self.cancel.store(false, Ordering::Relaxed);
+            return Err(RuntimeError::fatal(None, "Interrupted".to_string()));
+        }
+        self.usage.steps += 1;
+        if let Some(max) = self.limits.steps {
+            if self.usage.steps > max {
+                return Err(RuntimeError::fatal(
+                    None,
+                    format!("Exceeded the limit of {max} steps."),
+                ));
+            }
+        }
+        if let Some(deadline) = self.usage.deadline {
+            if self.usage.steps.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline {
+                let time = self.limits.time.unwrap_or_default();
+                return Err(RuntimeError::fatal(
+                    None,
+                    format!("Exceeded the time limit of {time:?}."),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn module_operand(
         &mut self,
         lox: &mut Lox,
         environment: EnvRef,
-        stmt: Stmt,
+        object: &Expr,
+        name: &Token,
+    ) -> Result<Module, RuntimeError> {
+        match self.evaluate(lox, environment, object)? {
+            LiteralVal::Module(module) => Ok(module),
+            _ => Err(RuntimeError::new(
+                Some(name.clone()),
+                "Only modules have properties.".to_string(),
+            )),
+        }
+    }
+
+    pub fn call(
+        &mut self,
+        lox: &mut Lox,
+        callee: LiteralVal,
+        paren: &Token,
+        arguments: Vec<LiteralVal>,
+    ) -> Result<LiteralVal, RuntimeError> {
+        match callee {
+            LiteralVal::Native(native) => {
+                check_arity(paren, native.arity, arguments.len())?;
+                (native.func)(self, lox, paren, arguments)
+            }
+            LiteralVal::Function(function) => {
+                let arity = function.arity();
+                check_arity(paren, (arity, arity), arguments.len())?;
+
+                let mut environment = Environment::with_enclosing(Rc::clone(&function.closure));
+                for (param, argument) in function.declaration.params.iter().zip(arguments) {
+                    environment.define(param.symbol, argument);
+                }
+
+                if self.frames.len() >= self.limits.frames {
+                    return Err(RuntimeError::new(
+                        Some(paren.clone()),
+                        "Stack overflow.".to_string(),
+                    ));
+                }
+                if let Some(caller) = self.frames.last_mut() {
+                    caller.line = paren.line;
+                }
+                let name = match &function.declaration.name {
+                    Some(name) => name.symbol,
+                    None => Symbol::intern("<fn>"),
+                };
+                self.frames.push(Frame {
+                    name,
+                    line: paren.line,
+                    environment: Rc::clone(&function.closure),
+                });
+                let flow = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+                    self.execute_block(lox, &function.declaration.body, environment)
+                });
+                self.frames.pop();
+                let flow = flow.map_err(|mut error| {
+                    error.trace.push(Call {
+                        function: name,
+                        line: paren.line,
+                    });
+                    error
+                })?;
+                match flow {
+                    Flow::Return(value) => Ok(value),
+                    _ => Ok(Nil),
+                }
+            }
+            _ => Err(RuntimeError::new(
+                Some(paren.clone()),
+                "Can only call functions.".to_string(),
+            )),
+        }
+    }
+
+    fn index_get(
+        &self,
+        object: &LiteralVal,
+        index: &LiteralVal,
+        bracket: &Token,
+    ) -> Result<LiteralVal, RuntimeError> {
+        match object {
+            LiteralVal::List(list) => {
+                let list = list.borrow();
+                Ok(list[index.index_operand(bracket, list.len())?].clone())
+            }
+            LiteralVal::Map(map) => {
+                let key = MapKey::new(index, bracket)?;
+                map.borrow().get(&key).cloned().ok_or_else(|| {
+                    RuntimeError::new(Some(bracket.clone()), format!("Key {key} not found."))
+                })
+            }
+            _ => Err(RuntimeError::new(
+                Some(bracket.clone()),
+                "Only lists and maps can be indexed.".to_string(),
+            )),
+        }
+    }
+
+    fn index_set(
+        &mut self,
+        object: &LiteralVal,
+        index: &LiteralVal,
+        value: LiteralVal,
+        bracket: &Token,
     ) -> Result<(), RuntimeError> {
+        match object {
+            LiteralVal::List(list) => {
+                let mut list = list.borrow_mut();
+                let index = index.index_operand(bracket, list.len())?;
+                list[index] = value;
+                Ok(())
+            }
+            LiteralVal::Map(map) => {
+                let key = MapKey::new(index, bracket)?;
+                if !map.borrow().contains_key(&key) {
+                    self.allocate(Some(bracket), MAP_ENTRY)?;
+                }
+                map.borrow_mut().insert(key, value);
+                Ok(())
+            }
+            _ => Err(RuntimeError::new(
+                Some(bracket.clone()),
+                "Only lists and maps can be indexed.".to_string(),
+            )),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        lox: &mut Lox,
+        environment: EnvRef,
+        stmt: &Stmt,
+    ) -> Result<Flow, RuntimeError> {
+        self.step()?;
+        if self.debugger.is_some() {
+            self.debug(lox, environment, stmt)?;
+        }
         match stmt {
-            Stmt::Expression(expression) => match self.evaluate(lox, environment, &expression) {
+            Stmt::Expression(expression) => match self.evaluate(lox, environment, expression) {
                 Err(e) => Err(e),
-                _ => Ok(()),
+                _ => Ok(Flow::Normal),
             },
 
-            Stmt::Print(expression) => {
-                let value = self.evaluate(lox, environment, &expression);
+            Stmt::Print(_, expression) => {
+                let value = self.evaluate(lox, environment, expression);
                 match value {
                     Ok(val) => {
-                        println!("{}", val.print()?);
-                        Ok(())
+                        lox.print(&val.print()?);
+                        Ok(Flow::Normal)
                     }
                     Err(e) => Err(e),
                 }
             }
 
-            Stmt::Var(name, init) => {
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.evaluate(lox, environment, condition)?;
+                if self.is_truthy(&condition) {
+                    self.execute(lox, environment, then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(lox, environment, else_branch)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                loop {
+                    let value = self.evaluate(lox, environment, condition)?;
+                    if !self.is_truthy(&value) {
+                        break;
+                    }
+                    match self.execute(lox, environment, body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => (),
+                    }
+                    if let Some(increment) = increment {
+                        self.evaluate(lox, environment, increment)?;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+
+            Stmt::Function(declaration) => {
+                let name = declaration.name.as_ref().unwrap().symbol;
+                let function = LoxFunction {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(environment),
+                };
+                environment
+                    .borrow_mut()
+                    .define(name, LiteralVal::Function(function));
+                Ok(Flow::Normal)
+            }
+
+            Stmt::Return(_, value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(lox, environment, value)?,
+                    None => Nil,
+                };
+                Ok(Flow::Return(value))
+            }
+
+            Stmt::Throw(keyword, value) => {
+                let value = self.evaluate(lox, environment, value)?;
+                Err(RuntimeError::throw(keyword, value))
+            }
+
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                let result = self.execute_block(
+                    lox,
+                    body,
+                    Environment::with_enclosing(Rc::clone(environment)),
+                );
+                let result = match (result, catch) {
+                    (Err(error), Some((name, handler))) if error.catchable => {
+                        let mut scope = Environment::with_enclosing(Rc::clone(environment));
+                        scope.define(name.symbol, error.caught());
+                        self.execute_block(lox, handler, scope)
+                    }
+                    (result, _) => result,
+                };
+                let Some(finally) = finally else {
+                    return result;
+                };
+                if matches!(&result, Err(error) if !error.catchable) {
+                    return result;
+                }
+                // Leaving `finally` early, or failing in it, wins over how
+                // the rest of the statement finished.
+                match self.execute_block(
+                    lox,
+                    finally,
+                    Environment::with_enclosing(Rc::clone(environment)),
+                )? {
+                    Flow::Normal => result,
+                    flow => Ok(flow),
+                }
+            }
+
+            Stmt::Break(_) => Ok(Flow::Break),
+
+            Stmt::Continue(_) => Ok(Flow::Continue),
+
+            Stmt::Var(name, _, init) => {
                 // To revert Chapter8/Challenge2, comment the line below and uncomment the one below that.
+                // `DefiniteAssignment` rejects most reads of an `UnInit` variable before
+                // running, and `Environment::get` catches the rest.
                 let mut value = LiteralVal::UnInit;
                 // let mut value = LiteralVal::Nil;
                 if let Some(expr) = init {
-                    value = self.evaluate(lox, environment, &expr)?;
+                    value = self.evaluate(lox, environment, expr)?;
                 }
 
-                environment.borrow_mut().define(name.lexeme, value);
-                Ok(())
+                environment.borrow_mut().define(name.symbol, value);
+                Ok(Flow::Normal)
             }
 
             Stmt::Block(statements) => self.execute_block(
                 lox,
-                &statements,
+                statements,
                 Environment::with_enclosing(Rc::clone(environment)),
             ),
+
+            Stmt::Import(path, name) => {
+                let module = self.import(lox, path)?;
+                environment
+                    .borrow_mut()
+                    .define(name.symbol, LiteralVal::Module(module));
+                Ok(Flow::Normal)
+            }
+        }
+    }
+
+    /// Resolves `path` relative to the file that is currently executing and
+    /// runs it in a fresh global environment, unless it was imported before.
+    fn import(&mut self, lox: &mut Lox, path: &Token) -> Result<Module, RuntimeError> {
+        if !self.capabilities.filesystem {
+            return Err(RuntimeError::new(
+                Some(path.clone()),
+                "Importing modules needs the filesystem capability.".to_string(),
+            ));
+        }
+        let relative = match &path.literal {
+            LiteralVal::Str(p) => p,
+            _ => unreachable!(),
+        };
+        let full = self
+            .modules
+            .current_dir()
+            .join(relative)
+            .canonicalize()
+            .map_err(|e| {
+                RuntimeError::new(
+                    Some(path.clone()),
+                    format!("Could not open module '{relative}': {e}."),
+                )
+            })?;
+
+        if let Some(module) = self.modules.get(&full) {
+            return Ok(module);
+        }
+
+        self.modules.enter(&full).map_err(|chain| {
+            RuntimeError::new(Some(path.clone()), format!("Import cycle: {chain}."))
+        })?;
+        let res = self.run_module(lox, path, &full);
+        self.modules.exit();
+
+        let module = res?;
+        self.modules.insert(full, module.clone());
+        Ok(module)
+    }
+
+    fn run_module(
+        &mut self,
+        lox: &mut Lox,
+        path: &Token,
+        full: &std::path::Path,
+    ) -> Result<Module, RuntimeError> {
+        let source = std::fs::read_to_string(full).map_err(|e| {
+            RuntimeError::new(
+                Some(path.clone()),
+                format!("Could not read module '{}': {e}.", full.display()),
+            )
+        })?;
+
+        // Syntax errors inside the module are reported as usual, but they
+        // surface to the importer as a runtime error of the import itself.
+        let had_error = std::mem::replace(&mut lox.had_error, false);
+        let tokens = Scanner::new(source).scan_tokens(lox);
+        let statements = Parser::new(tokens).parse(lox);
+        let mut assignment = DefiniteAssignment::new();
+        if !lox.had_error {
+            assignment.check(lox, &statements);
+            TypeChecker::new().check(lox, &statements);
+            TypeChecker::missing_returns(lox, assignment.falls_through());
+        }
+        if !lox.had_error {
+            Linter::new().check(lox, &statements);
+            Linter::unreachable(lox, assignment.unreachable());
+        }
+        let failed = lox.had_error;
+        lox.had_error = had_error;
+        if failed {
+            return Err(RuntimeError::new(
+                Some(path.clone()),
+                format!("Could not compile module '{}'.", full.display()),
+            ));
+        }
+
+        let environment = Rc::new(RefCell::new(Environment::global()));
+        let bindings = Rc::new(Module::declared(&statements));
+        let statements = lox.optimized(statements);
+        Resolver::new().resolve(&statements);
+        let name = full
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.frames.push(Frame {
+            name: Symbol::intern(&format!("<module {name}>")),
+            line: 0,
+            environment: Rc::clone(&environment),
+        });
+        let res = statements
+            .iter()
+            .try_for_each(|statement| self.execute(lox, &environment, statement).map(drop));
+        self.frames.pop();
+        res?;
+
+        Ok(Module {
+            name,
+            path: full.to_path_buf(),
+            environment,
+            bindings,
+        })
+    }
+
+    /// Gives the debugger a chance to stop before `stmt` runs. Blocks are
+    /// skipped, their statements stop on their own.
+    fn debug(
+        &mut self,
+        lox: &mut Lox,
+        environment: EnvRef,
+        stmt: &Stmt,
+    ) -> Result<(), RuntimeError> {
+        let line = match stmt {
+            Stmt::Block(_) => None,
+            _ => stmt.line(),
+        };
+        let Some(line) = line else {
+            return Ok(());
+        };
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+            frame.environment = Rc::clone(environment);
+        }
+
+        let mut debugger = self.debugger.take().expect("only called while debugging");
+        match debugger.statement(self, lox, stmt, line) {
+            Step::Detach => Ok(()),
+            Step::Stop => Err(RuntimeError::fatal(
+                None,
+                "Stopped by the debugger.".to_string(),
+            )),
+            _ => {
+                self.debugger = Some(debugger);
+                Ok(())
+            }
         }
     }
 
     fn execute_block(
         &mut self,
         lox: &mut Lox,
-        statements: &Vec<Stmt>,
+        statements: &[Stmt],
         environment: Environment,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<Flow, RuntimeError> {
         let er = Rc::new(RefCell::new(environment));
 
         for stmt in statements {
-            self.execute(lox, &er, stmt.clone())?;
+            let flow = self.execute(lox, &er, stmt)?;
+            if flow != Flow::Normal {
+                return Ok(flow);
+            }
         }
-        Ok(())
+        Ok(Flow::Normal)
     }
 
     pub fn is_truthy(&self, obj: &LiteralVal) -> bool {
-        match obj {
-            Nil | LiteralVal::Boolean(false) => false,
-            _ => true,
-        }
+        !matches!(obj, Nil | LiteralVal::Boolean(false))
     }
 
     pub fn is_equal(&self, a: &LiteralVal, b: &LiteralVal) -> bool {
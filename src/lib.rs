@@ -1,24 +1,66 @@
-use std::{cell::RefCell, io::Write, path::Path, rc::Rc};
+use std::{
+    cell::RefCell, collections::HashSet, io::Write, path::Path, rc::Rc, sync::atomic::Ordering,
+};
 
 pub mod interpreter;
 pub mod parser;
+pub mod printer;
 pub mod scanner;
+use definite_assignment::DefiniteAssignment;
+use diagnostic::Diagnostic;
 use environment::Environment;
 use interpreter::{Interpreter, RuntimeError};
+use lint::{Lint, Linter};
+use optimizer::Optimizer;
 use parser::Parser;
+use resolver::Resolver;
 use scanner::Scanner;
+use session::Snapshot;
 use stmt::Stmt;
+use timer::PhaseTimer;
 use token::{Token, TokenType};
+use types::TypeChecker;
+pub mod analysis;
+pub mod callable;
+pub mod dap;
+pub mod debugger;
+pub mod definite_assignment;
+pub mod diagnostic;
 pub mod environment;
 pub mod expr;
+pub mod lint;
+pub mod lsp;
+pub mod map;
+pub mod module;
+pub mod natives;
+pub mod optimizer;
+pub mod resolver;
+pub mod sandbox;
+pub mod session;
 pub mod stmt;
+pub mod symbol;
+mod timer;
 pub mod token;
+pub mod types;
 
 type EnvRef<'a> = &'a Rc<RefCell<Environment>>;
 
 pub struct Lox {
     had_error: bool,
     had_runtime_error: bool,
+    /// Warnings that were silenced with `allow`.
+    allowed: HashSet<Lint>,
+    /// Whether programs go through the `Optimizer` before running.
+    optimize: bool,
+    /// Whether `run` reports how long each phase took to `diagnostics`.
+    time: bool,
+    /// Where `print` writes to. Standard output unless replaced.
+    output: Box<dyn Write>,
+    /// Where errors and warnings are reported. Standard error unless
+    /// replaced.
+    diagnostics: Box<dyn Write>,
+    /// Everything reported to `diagnostics` before the program ran.
+    reported: Vec<Diagnostic>,
 }
 
 impl Default for Lox {
@@ -26,6 +68,12 @@ impl Default for Lox {
         Self {
             had_error: false,
             had_runtime_error: false,
+            allowed: HashSet::new(),
+            optimize: false,
+            time: false,
+            output: Box::new(std::io::stdout()),
+            diagnostics: Box::new(std::io::stderr()),
+            reported: vec![],
         }
     }
 }
@@ -38,34 +86,44 @@ impl Lox {
         environment: EnvRef,
         repl: bool,
     ) {
-        let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens(self);
-        // for token in tokens.clone() {
-        //     println!("{}", token.to_string());
-        // }
-
-        let mut parser = Parser::new(tokens);
-        let statements = parser.parse(self);
+        let mut timer = PhaseTimer::new(self.time);
+        self.run_timed(source, interpreter, environment, repl, &mut timer);
+        timer.report(&mut self.diagnostics).unwrap();
+    }
 
+    fn run_timed(
+        &mut self,
+        source: String,
+        interpreter: &mut Interpreter,
+        environment: EnvRef,
+        repl: bool,
+        timer: &mut PhaseTimer,
+    ) {
+        let statements = self.front_end(source, timer);
         if self.had_error {
             return;
         }
 
+        let statements = self.optimized(statements);
+        if self.optimize {
+            timer.lap("optimize");
+        }
+        Resolver::new().resolve(&statements);
+        timer.lap("resolve");
+
         let should_interpret = if repl && statements.len() == 1 {
             match &statements[0] {
-                Stmt::Expression(expr) => match interpreter.evaluate(self, environment, expr) {
-                    Ok(val) => {
-                        match val.print() {
-                            Ok(s) => println!("{s}"),
+                Stmt::Expression(expr) => {
+                    interpreter.begin_run();
+                    match interpreter.evaluate(self, environment, expr) {
+                        Ok(val) => match val.print() {
+                            Ok(s) => self.print(&s),
                             Err(e) => self.runtime_error(e),
-                        }
-                        false
-                    }
-                    Err(e) => {
-                        self.runtime_error(e);
-                        false
+                        },
+                        Err(e) => self.runtime_error(e),
                     }
-                },
+                    false
+                }
                 _ => true,
             }
         } else {
@@ -73,28 +131,188 @@ impl Lox {
         };
 
         if should_interpret {
-            interpreter.interpret(self, statements, environment);
+            interpreter.interpret(self, &statements, environment);
         }
+        timer.lap("execute");
+    }
+
+    /// Scans, parses and checks `source` without running it, reporting
+    /// errors and warnings. The statements that could be parsed are
+    /// returned even if there were errors.
+    pub fn check(&mut self, source: String) -> Vec<Stmt> {
+        self.front_end(source, &mut PhaseTimer::new(false))
+    }
+
+    fn front_end(&mut self, source: String, timer: &mut PhaseTimer) -> Vec<Stmt> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens(self);
+        // for token in tokens.clone() {
+        //     println!("{}", token.to_string());
+        // }
+        timer.lap("scan");
+
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse(self);
+        timer.lap("parse");
+
+        if self.had_error {
+            return statements;
+        }
+
+        let mut assignment = DefiniteAssignment::new();
+        assignment.check(self, &statements);
+        TypeChecker::new().check(self, &statements);
+        TypeChecker::missing_returns(self, assignment.falls_through());
+        // Lints assume the program is otherwise correct.
+        if !self.had_error {
+            Linter::new().check(self, &statements);
+            Linter::unreachable(self, assignment.unreachable());
+        }
+        timer.lap("check");
+        statements
     }
 
     pub fn error(&mut self, line: u32, message: &str) {
+        self.reported.push(Diagnostic {
+            line,
+            token: None,
+            message: message.to_string(),
+            lint: None,
+        });
         self.report(line, "", message);
     }
 
+    /// Reports `err` with the calls it happened in, innermost first. Runs
+    /// of the same call, as in deep recursion, are cut short.
     pub fn runtime_error(&mut self, err: RuntimeError) {
-        match err.0 {
-            Some(op) => eprintln!("{}\n[line {};token {}]", err.1, op.line, op.lexeme),
-            None => eprintln!("{}", err.1),
-        };
+        match err.token {
+            Some(op) => writeln!(
+                self.diagnostics,
+                "{}\n[line {};token {}]",
+                err.message, op.line, op.lexeme
+            ),
+            None => writeln!(self.diagnostics, "{}", err.message),
+        }
+        .unwrap();
+        let mut calls = err.trace.iter().peekable();
+        while let Some(call) = calls.next() {
+            let mut repeated = 0;
+            while calls.next_if(|next| *next == call).is_some() {
+                repeated += 1;
+            }
+            let line = format!("  in {}, called on line {}", call.function, call.line);
+            if repeated <= 2 {
+                for _ in 0..=repeated {
+                    writeln!(self.diagnostics, "{line}").unwrap();
+                }
+            } else {
+                writeln!(self.diagnostics, "{line}\n{line}").unwrap();
+                writeln!(
+                    self.diagnostics,
+                    "  ... repeated {} more times",
+                    repeated - 1
+                )
+                .unwrap();
+            }
+        }
         self.had_runtime_error = true;
     }
 
     pub fn report(&mut self, line: u32, where_: &str, message: &str) {
-        eprintln!("[line {line}] Error{where_}: {message}");
+        writeln!(self.diagnostics, "[line {line}] Error{where_}: {message}").unwrap();
         self.had_error = true;
     }
 
+    pub fn allow(&mut self, lint: Lint) {
+        self.allowed.insert(lint);
+    }
+
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    pub fn set_time(&mut self, time: bool) {
+        self.time = time;
+    }
+
+    /// Sends the output of `print` statements to `output` instead of
+    /// standard output.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Sends errors and warnings to `diagnostics` instead of standard error.
+    pub fn set_diagnostics(&mut self, diagnostics: impl Write + 'static) {
+        self.diagnostics = Box::new(diagnostics);
+    }
+
+    pub fn had_error(&self) -> bool {
+        self.had_error
+    }
+
+    /// Errors and warnings reported so far, in order.
+    pub fn reported(&self) -> &[Diagnostic] {
+        &self.reported
+    }
+
+    pub fn had_runtime_error(&self) -> bool {
+        self.had_runtime_error
+    }
+
+    /// The status to exit with after running a file, from sysexits.h as in
+    /// the book: 65 for compile errors and 70 for runtime errors.
+    pub fn exit_code(&self) -> i32 {
+        if self.had_error {
+            65
+        } else if self.had_runtime_error {
+            70
+        } else {
+            0
+        }
+    }
+
+    /// Writes one line of program output.
+    pub fn print(&mut self, text: &str) {
+        writeln!(self.output, "{text}").unwrap();
+    }
+
+    /// Runs the optimizer over checked statements if it is enabled.
+    pub(crate) fn optimized(&self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        if self.optimize {
+            Optimizer::new().optimize(statements)
+        } else {
+            statements
+        }
+    }
+
+    /// Reports a possible mistake. Unlike errors, warnings don't stop the
+    /// program from running.
+    pub fn warning(&mut self, token: &Token, lint: Lint, message: &str) {
+        if !self.allowed.contains(&lint) {
+            self.reported.push(Diagnostic {
+                line: token.line,
+                token: Some(token.clone()),
+                message: message.to_string(),
+                lint: Some(lint),
+            });
+            writeln!(
+                self.diagnostics,
+                "[line {}] Warning at '{}': {message} [{}]",
+                token.line,
+                token.lexeme,
+                lint.code()
+            )
+            .unwrap();
+        }
+    }
+
     pub fn error_token(&mut self, token: &Token, message: &str) {
+        self.reported.push(Diagnostic {
+            line: token.line,
+            token: Some(token.clone()),
+            message: message.to_string(),
+            lint: None,
+        });
         if token.token_type == TokenType::EOF {
             self.report(token.line, " at end", message)
         } else {
@@ -112,24 +330,78 @@ impl Lox {
         interpreter: &mut Interpreter,
         environment: EnvRef,
     ) {
-        let source = std::fs::read_to_string(file).unwrap();
+        let source = std::fs::read_to_string(&file).unwrap();
+        let path = file.as_ref().canonicalize().unwrap();
+        // The main script counts as a module too, so imports resolve
+        // relative to it and importing it back is reported as a cycle.
+        interpreter.modules.enter(&path).unwrap();
         self.run(source, interpreter, environment, false);
-        if self.had_error {
-            panic!("had error")
-        }
+        interpreter.modules.exit();
     }
 
+    /// Reads and runs lines until the end of the input. Cancelling a line,
+    /// as Ctrl-C does, leaves the globals it defined so far in place.
+    ///
+    /// Lines starting with `:` are commands: `:save PATH` writes the
+    /// globals to a file and `:load-session PATH` runs one written before.
     pub fn run_prompt(&mut self, interpreter: &mut Interpreter, environment: EnvRef) {
         loop {
             self.had_error = false;
+            self.reported.clear();
             print!("> ");
             std::io::stdout().flush().unwrap();
             let mut line = String::new();
             match std::io::stdin().read_line(&mut line) {
+                Ok(0) => {
+                    println!();
+                    return;
+                }
                 Ok(_) => (),
                 Err(_) => panic!("couldn't read from stdin"),
             }
+            // A Ctrl-C at the prompt shouldn't cancel the next line.
+            interpreter.cancel.store(false, Ordering::Relaxed);
+            if let Some(command) = line.trim().strip_prefix(':') {
+                self.command(command, interpreter, environment);
+                continue;
+            }
             self.run(line, interpreter, environment, true);
         }
     }
+
+    /// Runs a REPL command, given without its `:`.
+    fn command(&mut self, command: &str, interpreter: &mut Interpreter, environment: EnvRef) {
+        let (name, path) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, path)| (name, path.trim()));
+        match name {
+            "save" | "load-session" if path.is_empty() => {
+                writeln!(self.diagnostics, "Usage: :{name} PATH").unwrap();
+            }
+            "save" => {
+                let snapshot = Snapshot::take(environment);
+                if let Err(err) = snapshot.save(path) {
+                    writeln!(self.diagnostics, "Couldn't write '{path}': {err}").unwrap();
+                    return;
+                }
+                self.print(&format!(
+                    "Saved {} globals to '{path}'.",
+                    snapshot.saved.len()
+                ));
+                for (name, reason) in snapshot.skipped {
+                    self.print(&format!("Skipped '{}': {reason}.", name.name()));
+                }
+            }
+            "load-session" => {
+                if let Err(err) = session::restore(self, interpreter, environment, path) {
+                    writeln!(self.diagnostics, "Couldn't read '{path}': {err}").unwrap();
+                }
+            }
+            _ => writeln!(
+                self.diagnostics,
+                "Unknown command ':{name}'. Try :save PATH or :load-session PATH."
+            )
+            .unwrap(),
+        }
+    }
 }
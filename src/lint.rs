@@ -0,0 +1,328 @@
+use crate::{
+    expr::Expr,
+    stmt::{FunctionDecl, Stmt},
+    token::{LiteralVal, Token, TokenType},
+    Lox,
+};
+
+/// Kinds of warnings. Each has a code that can be passed to `Lox::allow`
+/// (or `--allow` on the command line) to silence it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedLocal,
+    UnusedParameter,
+    UnreachableCode,
+    Shadowing,
+    ConstantCondition,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedLocal,
+        Lint::UnusedParameter,
+        Lint::UnreachableCode,
+        Lint::Shadowing,
+        Lint::ConstantCondition,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedLocal => "unused-local",
+            Self::UnusedParameter => "unused-parameter",
+            Self::UnreachableCode => "unreachable-code",
+            Self::Shadowing => "shadowing",
+            Self::ConstantCondition => "constant-condition",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lint| lint.code() == code)
+    }
+}
+
+struct Local {
+    name: Token,
+    parameter: bool,
+    used: bool,
+}
+
+/// Warnings pass over the AST. Globals are never reported as unused or
+/// shadowed, since other files and later REPL lines can see them. Names
+/// starting with `_` are never reported as unused.
+#[derive(Default)]
+pub struct Linter {
+    /// Local scopes, innermost last. Top-level code has none.
+    scopes: Vec<Vec<Local>>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, lox: &mut Lox, statements: &[Stmt]) {
+        // Functions can call the ones declared further down, so those are
+        // in scope from the start.
+        for statement in statements {
+            if let Stmt::Function(declaration) = statement {
+                if let Some(name) = &declaration.name {
+                    self.declare(lox, name, false);
+                }
+            }
+        }
+        for statement in statements {
+            self.statement(lox, statement);
+        }
+    }
+
+    /// Warns where code that can't be reached starts. `DefiniteAssignment`
+    /// already follows every path through the program, so it finds them.
+    pub fn unreachable(lox: &mut Lox, starts: &[Token]) {
+        for start in starts {
+            lox.warning(start, Lint::UnreachableCode, "Code is never executed.");
+        }
+    }
+
+    fn statement(&mut self, lox: &mut Lox, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(statements) => self.block(lox, statements),
+            Stmt::Break(_) | Stmt::Continue(_) => (),
+            Stmt::Expression(expr) | Stmt::Print(_, expr) | Stmt::Throw(_, expr) => {
+                self.expression(lox, expr)
+            }
+            Stmt::Function(declaration) => self.function(lox, declaration),
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.condition(lox, keyword, condition);
+                self.statement(lox, then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(lox, else_branch);
+                }
+            }
+            Stmt::Import(_, name) => self.declare(lox, name, false),
+            Stmt::Return(_, value) => {
+                if let Some(value) = value {
+                    self.expression(lox, value);
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.block(lox, body);
+                if let Some((name, handler)) = catch {
+                    self.begin_scope();
+                    self.declare(lox, name, false);
+                    self.check(lox, handler);
+                    self.end_scope(lox);
+                }
+                if let Some(finally) = finally {
+                    self.block(lox, finally);
+                }
+            }
+            Stmt::Var(name, _, initializer) => {
+                if let Some(initializer) = initializer {
+                    self.expression(lox, initializer);
+                }
+                self.declare(lox, name, false);
+            }
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+                increment,
+            } => {
+                // `while (true)` and `for (;;)` are the usual way to write an
+                // endless loop, not a mistake.
+                if !matches!(condition, Expr::Literal(LiteralVal::Boolean(true))) {
+                    self.condition(lox, keyword, condition);
+                }
+                self.statement(lox, body);
+                if let Some(increment) = increment {
+                    self.expression(lox, increment);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, lox: &mut Lox, statements: &[Stmt]) {
+        self.begin_scope();
+        self.check(lox, statements);
+        self.end_scope(lox);
+    }
+
+    fn condition(&mut self, lox: &mut Lox, keyword: &Token, condition: &Expr) {
+        if is_constant(condition) {
+            lox.warning(
+                keyword,
+                Lint::ConstantCondition,
+                &format!("Condition of '{}' is always the same.", keyword.lexeme),
+            );
+        }
+        self.expression(lox, condition);
+    }
+
+    fn expression(&mut self, lox: &mut Lox, expr: &Expr) {
+        match expr {
+            Expr::Assign(_, value, _) => self.expression(lox, value),
+            Expr::Binary { left, right, .. } => {
+                self.expression(lox, left);
+                self.expression(lox, right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(lox, callee);
+                for argument in arguments {
+                    self.expression(lox, argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(lox, condition);
+                self.expression(lox, then_branch);
+                self.expression(lox, else_branch);
+            }
+            Expr::Compound { target, value, .. } => {
+                self.expression(lox, target);
+                self.expression(lox, value);
+            }
+            Expr::Get(object, _) => self.expression(lox, object),
+            Expr::Grouping(expr) => self.expression(lox, expr),
+            Expr::Index { object, index, .. } => {
+                self.expression(lox, object);
+                self.expression(lox, index);
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(lox, object);
+                self.expression(lox, index);
+                self.expression(lox, value);
+            }
+            Expr::Lambda(declaration) => self.function(lox, declaration),
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(lox, element);
+                }
+            }
+            Expr::Literal(_) => (),
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.expression(lox, key);
+                    self.expression(lox, value);
+                }
+            }
+            Expr::Set(object, _, value) => {
+                self.expression(lox, object);
+                self.expression(lox, value);
+            }
+            Expr::Unary { right, .. } => self.expression(lox, right),
+            Expr::Variable(name, _) => self.use_variable(name),
+        }
+    }
+
+    /// The body gets a scope of its own, so a local that reuses the name of
+    /// a parameter is reported as shadowing it.
+    fn function(&mut self, lox: &mut Lox, declaration: &FunctionDecl) {
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(lox, param, true);
+        }
+        self.block(lox, &declaration.body);
+        self.end_scope(lox);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    fn end_scope(&mut self, lox: &mut Lox) {
+        let scope = self.scopes.pop().unwrap();
+        for local in scope {
+            if local.used || local.name.lexeme.starts_with('_') {
+                continue;
+            }
+            if local.parameter {
+                lox.warning(
+                    &local.name,
+                    Lint::UnusedParameter,
+                    &format!("Parameter '{}' is never used.", local.name.lexeme),
+                );
+            } else {
+                lox.warning(
+                    &local.name,
+                    Lint::UnusedLocal,
+                    &format!("Local variable '{}' is never used.", local.name.lexeme),
+                );
+            }
+        }
+    }
+
+    fn declare(&mut self, lox: &mut Lox, name: &Token, parameter: bool) {
+        let Some((current, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+
+        if let Some(outer) = enclosing
+            .iter()
+            .flatten()
+            .rev()
+            .find(|local| local.name.lexeme == name.lexeme)
+        {
+            lox.warning(
+                name,
+                Lint::Shadowing,
+                &format!(
+                    "'{}' shadows a local declared on line {}.",
+                    name.lexeme, outer.name.line
+                ),
+            );
+        }
+
+        current.push(Local {
+            name: name.clone(),
+            parameter,
+            used: false,
+        });
+    }
+
+    fn use_variable(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name.lexeme == name.lexeme)
+        {
+            local.used = true;
+        }
+    }
+}
+
+/// Whether `expr` always evaluates to the same value.
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Grouping(expr) => is_constant(expr),
+        Expr::Unary { right, .. } => is_constant(right),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => operator.token_type != TokenType::Comma && is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
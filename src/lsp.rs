@@ -0,0 +1,401 @@
+//! A language server for Lox. Documents are checked like `Lox::run` checks
+//! a program, without running it, and `Analysis` answers questions about
+//! their variables.
+
+use std::{collections::HashMap, error::Error, io};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References,
+        Request as LspRequest,
+    },
+    CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse,
+    Diagnostic as LspDiagnostic, DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams,
+    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString,
+    OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams, ServerCapabilities,
+    SymbolKind, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+
+use crate::{
+    analysis::{Analysis, DeclarationKind},
+    diagnostic::Diagnostic,
+    natives,
+    scanner::KEYWORDS,
+    token::Token,
+    Lox,
+};
+
+/// What the server supports, sent in reply to `initialize`.
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(Default::default()),
+        ..Default::default()
+    }
+}
+
+/// Answers requests on `connection` until the client shuts the server
+/// down.
+pub fn serve(connection: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(published) = server.notification(notification) {
+                    connection.sender.send(Message::Notification(published))?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+/// An open file and what checking it found.
+struct Document {
+    text: String,
+    analysis: Analysis,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let mut lox = Lox::default();
+        lox.set_diagnostics(io::sink());
+        let statements = lox.check(text.clone());
+        Self {
+            analysis: Analysis::new(&statements),
+            diagnostics: lox.reported().to_vec(),
+            text,
+        }
+    }
+
+    fn line(&self, line: u32) -> &str {
+        self.text.lines().nth(line as usize - 1).unwrap_or_default()
+    }
+
+    /// Converts a line and column counted in `char`s from 1 to a position
+    /// counted in UTF-16 code units from 0.
+    fn position(&self, line: u32, column: u32) -> Position {
+        let character = self
+            .line(line)
+            .chars()
+            .take(column as usize - 1)
+            .map(char::len_utf16)
+            .sum::<usize>();
+        Position::new(line - 1, character as u32)
+    }
+
+    /// The opposite of `position`.
+    fn line_and_column(&self, position: Position) -> (u32, u32) {
+        let line = position.line + 1;
+        let mut units = 0;
+        let column = self
+            .line(line)
+            .chars()
+            .take_while(|c| {
+                units += c.len_utf16() as u32;
+                units <= position.character
+            })
+            .count();
+        (line, column as u32 + 1)
+    }
+
+    fn range(&self, token: &Token) -> Range {
+        let start = self.position(token.line, token.column);
+        let length = token.lexeme.chars().map(char::len_utf16).sum::<usize>();
+        let end = Position::new(start.line, start.character + length as u32);
+        Range::new(start, end)
+    }
+
+    /// The index of the declaration of the name at `position`.
+    fn declaration_at(&self, position: Position) -> Option<usize> {
+        let (line, column) = self.line_and_column(position);
+        let name = self.analysis.name_at(line, column)?;
+        self.analysis.declaration_of(name)
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) -> LspDiagnostic {
+        let range = match &diagnostic.token {
+            Some(token) => self.range(token),
+            // Scanner errors only know the line.
+            None => {
+                let end = self.line(diagnostic.line).chars().count() as u32 + 1;
+                Range::new(
+                    self.position(diagnostic.line, 1),
+                    self.position(diagnostic.line, end),
+                )
+            }
+        };
+        let (severity, code) = match diagnostic.lint {
+            Some(lint) => (
+                DiagnosticSeverity::WARNING,
+                Some(NumberOrString::String(lint.code().to_string())),
+            ),
+            None => (DiagnosticSeverity::ERROR, None),
+        };
+        LspDiagnostic {
+            range,
+            severity: Some(severity),
+            code,
+            source: Some("lox".to_string()),
+            message: diagnostic.message.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Uri, Document>,
+}
+
+impl Server {
+    fn request(&mut self, request: Request) -> Response {
+        match request.method.as_str() {
+            GotoDefinition::METHOD => respond::<GotoDefinition>(request, |p| self.definition(p)),
+            References::METHOD => respond::<References>(request, |p| self.references(p)),
+            HoverRequest::METHOD => respond::<HoverRequest>(request, |p| self.hover(p)),
+            DocumentSymbolRequest::METHOD => {
+                respond::<DocumentSymbolRequest>(request, |p| self.document_symbols(p))
+            }
+            Completion::METHOD => respond::<Completion>(request, |p| self.completion(p)),
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request '{method}'."),
+            ),
+        }
+    }
+
+    /// Handles a notification, returning the diagnostics to publish if a
+    /// document changed.
+    fn notification(&mut self, notification: Notification) -> Option<Notification> {
+        let (uri, document) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = params::<DidOpenTextDocument>(notification)?;
+                let document = Document::new(params.text_document.text);
+                (params.text_document.uri, document)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = params::<DidChangeTextDocument>(notification)?;
+                // Changes are always whole documents, see `capabilities`.
+                let text = params.content_changes.into_iter().last()?.text;
+                (params.text_document.uri, Document::new(text))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = params::<DidCloseTextDocument>(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                return Some(publish(params.text_document.uri, vec![]));
+            }
+            _ => return None,
+        };
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| document.diagnostic(diagnostic))
+            .collect();
+        self.documents.insert(uri.clone(), document);
+        Some(publish(uri, diagnostics))
+    }
+
+    fn document(&self, position: &TextDocumentPositionParams) -> Option<&Document> {
+        self.documents.get(&position.text_document.uri)
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let document = self.document(&position)?;
+        let index = document.declaration_at(position.position)?;
+        let name = &document.analysis.declarations[index].name;
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            position.text_document.uri,
+            document.range(name),
+        )))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let document = self.document(&position)?;
+        let index = document.declaration_at(position.position)?;
+        let uri = position.text_document.uri;
+        let mut names: Vec<_> = document
+            .analysis
+            .references_to(index)
+            .map(|reference| &reference.name)
+            .collect();
+        if params.context.include_declaration {
+            names.push(&document.analysis.declarations[index].name);
+        }
+        // Assignments are recorded after their values, so sort by position.
+        names.sort_by_key(|name| (name.line, name.column));
+        let locations = names
+            .into_iter()
+            .map(|name| Location::new(uri.clone(), document.range(name)))
+            .collect();
+        Some(locations)
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let document = self.document(&position)?;
+        let (line, column) = document.line_and_column(position.position);
+        let name = document.analysis.name_at(line, column)?;
+        let text = match document.analysis.declaration_of(name) {
+            Some(index) => {
+                let declaration = &document.analysis.declarations[index];
+                let kind = match declaration.kind {
+                    DeclarationKind::Variable => "Variable",
+                    DeclarationKind::Function => "Function",
+                    DeclarationKind::Parameter => "Parameter",
+                    DeclarationKind::Module => "Module",
+                };
+                format!(
+                    "```lox\n{}\n```\n{kind} declared on line {}.",
+                    declaration.detail, declaration.name.line
+                )
+            }
+            None => {
+                let native = natives::all()
+                    .into_iter()
+                    .find(|native| native.name == name.lexeme)?;
+                let (min, max) = native.arity;
+                let arity = if min == max {
+                    format!("{min}")
+                } else {
+                    format!("{min} to {max}")
+                };
+                format!("```lox\n{native:?}\n```\nBuilt-in function taking {arity} arguments.")
+            }
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: text,
+            }),
+            range: Some(document.range(name)),
+        })
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        Some(DocumentSymbolResponse::Nested(symbols(document, None)))
+    }
+
+    /// Keywords, built-ins and every name declared in the document.
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let document = self.document(&params.text_document_position)?;
+        let mut items: Vec<CompletionItem> = vec![];
+        let mut add = |label: &str, kind, detail: Option<String>| {
+            if items.iter().all(|item| item.label != label) {
+                items.push(CompletionItem {
+                    label: label.to_string(),
+                    kind: Some(kind),
+                    detail,
+                    ..Default::default()
+                });
+            }
+        };
+        for declaration in &document.analysis.declarations {
+            add(
+                &declaration.name.lexeme,
+                completion_kind(declaration.kind),
+                Some(declaration.detail.clone()),
+            );
+        }
+        for native in natives::all() {
+            add(native.name, CompletionItemKind::FUNCTION, None);
+        }
+        for (keyword, _) in KEYWORDS {
+            add(keyword, CompletionItemKind::KEYWORD, None);
+        }
+        Some(CompletionResponse::Array(items))
+    }
+}
+
+/// The symbols declared directly inside the function declared at `parent`,
+/// or at the top level.
+fn symbols(document: &Document, parent: Option<usize>) -> Vec<DocumentSymbol> {
+    document
+        .analysis
+        .declarations
+        .iter()
+        .enumerate()
+        .filter(|(_, declaration)| declaration.parent == parent)
+        .map(|(index, declaration)| {
+            let range = document.range(&declaration.name);
+            let (kind, children) = match declaration.kind {
+                DeclarationKind::Function => {
+                    (SymbolKind::FUNCTION, Some(symbols(document, Some(index))))
+                }
+                DeclarationKind::Module => (SymbolKind::MODULE, None),
+                DeclarationKind::Variable | DeclarationKind::Parameter => {
+                    (SymbolKind::VARIABLE, None)
+                }
+            };
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: declaration.name.lexeme.clone(),
+                detail: Some(declaration.detail.clone()),
+                kind,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range: range,
+                children,
+            }
+        })
+        .collect()
+}
+
+fn completion_kind(kind: DeclarationKind) -> CompletionItemKind {
+    match kind {
+        DeclarationKind::Function => CompletionItemKind::FUNCTION,
+        DeclarationKind::Module => CompletionItemKind::MODULE,
+        DeclarationKind::Variable | DeclarationKind::Parameter => CompletionItemKind::VARIABLE,
+    }
+}
+
+fn respond<R: LspRequest>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(error) => Response::new_err(
+            request.id,
+            ErrorCode::InvalidParams as i32,
+            error.to_string(),
+        ),
+    }
+}
+
+/// The parameters of `notification`, or `None` if they're malformed.
+fn params<N: LspNotification>(notification: Notification) -> Option<N::Params> {
+    serde_json::from_value(notification.params).ok()
+}
+
+fn publish(uri: Uri, diagnostics: Vec<LspDiagnostic>) -> Notification {
+    Notification::new(
+        PublishDiagnostics::METHOD.to_string(),
+        PublishDiagnosticsParams::new(uri, diagnostics, None),
+    )
+}
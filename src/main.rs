@@ -1,22 +1,82 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{atomic::Ordering, Arc},
+};
 
 use clap::Parser;
-use crafting_interpreters::{environment::Environment, interpreter::Interpreter, Lox};
+use crafting_interpreters::{
+    debugger::{Console, Debugger},
+    environment::Environment,
+    interpreter::Interpreter,
+    lint::Lint,
+    sandbox::Capabilities,
+    Lox,
+};
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[arg(short, long)]
     file: Option<String>,
+    /// Silence a warning, e.g. `--allow shadowing`. Can be repeated.
+    #[arg(short, long, value_parser = parse_lint)]
+    allow: Vec<Lint>,
+    /// Fold constants and remove dead branches before running.
+    #[arg(short = 'O', long)]
+    optimize: bool,
+    /// Print how long scanning, parsing, checking and running took.
+    #[arg(long)]
+    time: bool,
+    /// Step through the program, stopping before its first line. Type
+    /// `help` at the `(debug)` prompt for the commands.
+    #[arg(long, requires = "file")]
+    debug: bool,
+}
+
+fn parse_lint(code: &str) -> Result<Lint, String> {
+    Lint::from_code(code).ok_or_else(|| {
+        let codes: Vec<_> = Lint::ALL.iter().map(Lint::code).collect();
+        format!(
+            "unknown warning '{code}', expected one of: {}",
+            codes.join(", ")
+        )
+    })
 }
 
 fn main() {
-    let Args { file } = Args::parse();
+    let Args {
+        file,
+        allow,
+        optimize,
+        time,
+        debug,
+    } = Args::parse();
     let mut lox = Lox::default();
+    lox.set_optimize(optimize);
+    lox.set_time(time);
+    for lint in allow {
+        lox.allow(lint);
+    }
     let mut interpreter = Interpreter::new();
-    let environment = Environment::new();
+    interpreter.capabilities = Capabilities::ALL;
+    let environment = Environment::global();
     let env_ref = Rc::new(RefCell::new(environment));
     match file {
-        Some(f) => lox.run_file(f, &mut interpreter, &env_ref),
-        None => lox.run_prompt(&mut interpreter, &env_ref),
+        Some(f) => {
+            if debug {
+                let source = std::fs::read_to_string(&f).unwrap_or_default();
+                let console = Console::new(&source, std::io::stdin().lock(), std::io::stdout());
+                interpreter.debugger = Some(Debugger::new(console).stop_on_entry());
+            }
+            lox.run_file(f, &mut interpreter, &env_ref);
+            std::process::exit(lox.exit_code());
+        }
+        None => {
+            // Ctrl-C stops the line that's running instead of the REPL.
+            let cancel = Arc::clone(&interpreter.cancel);
+            ctrlc::set_handler(move || cancel.store(true, Ordering::Relaxed))
+                .expect("couldn't handle Ctrl-C");
+            lox.run_prompt(&mut interpreter, &env_ref)
+        }
     }
 }
@@ -0,0 +1,108 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    interpreter::RuntimeError,
+    token::{LiteralVal, Token},
+};
+
+/// A value that can be used as a map key. Numbers are stored by their bits
+/// after normalisation, so keys agree with `Interpreter::is_equal`: `-0` and
+/// `0` are the same key and so are all NaNs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Number(u32),
+    NaN,
+    Str(String),
+}
+
+impl MapKey {
+    pub fn new(value: &LiteralVal, token: &Token) -> Result<Self, RuntimeError> {
+        match value {
+            LiteralVal::Number(n) if n.is_nan() => Ok(Self::NaN),
+            LiteralVal::Number(n) if *n == 0. => Ok(Self::Number(0f32.to_bits())),
+            LiteralVal::Number(n) => Ok(Self::Number(n.to_bits())),
+            LiteralVal::NaN => Ok(Self::NaN),
+            LiteralVal::Str(s) => Ok(Self::Str(s.clone())),
+            _ => Err(RuntimeError::new(
+                Some(token.clone()),
+                "Map keys must be numbers or strings.".to_string(),
+            )),
+        }
+    }
+
+    pub fn to_value(&self) -> LiteralVal {
+        match self {
+            Self::Number(bits) => LiteralVal::Number(f32::from_bits(*bits)),
+            Self::NaN => LiteralVal::NaN,
+            Self::Str(s) => LiteralVal::Str(s.clone()),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Keys are never uninitialised, so `repr` can't fail.
+        write!(f, "{}", self.to_value().repr().unwrap_or_default())
+    }
+}
+
+/// Map that remembers insertion order, so printing and `keys()` are
+/// deterministic.
+#[derive(Clone, Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, LiteralVal)>,
+    indices: HashMap<MapKey, usize>,
+}
+
+impl LoxMap {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&LiteralVal> {
+        self.indices.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: MapKey, value: LiteralVal) {
+        match self.indices.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<LiteralVal> {
+        let i = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for index in self.indices.values_mut() {
+            if *index > i {
+                *index -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(MapKey, LiteralVal)> {
+        self.entries.iter()
+    }
+}
+
+impl PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .entries
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
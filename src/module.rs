@@ -0,0 +1,129 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    environment::Environment,
+    interpreter::RuntimeError,
+    stmt::Stmt,
+    symbol::Symbol,
+    token::{LiteralVal, Token},
+};
+
+/// A `.lox` file that has been imported. Its top-level bindings live in
+/// `environment` and are reachable through `name.binding`.
+#[derive(Clone)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub environment: Rc<RefCell<Environment>>,
+    /// Names the module declares itself. Its environment also has the
+    /// built-in functions, which aren't part of the module.
+    pub bindings: Rc<HashSet<Symbol>>,
+}
+
+impl Module {
+    pub fn get(&self, name: &Token) -> Result<LiteralVal, RuntimeError> {
+        self.binding(name)?;
+        self.environment.borrow().get(name)
+    }
+
+    pub fn set(&self, name: &Token, value: LiteralVal) -> Result<(), RuntimeError> {
+        self.binding(name)?;
+        self.environment.borrow_mut().assign(name, value)
+    }
+
+    fn binding(&self, name: &Token) -> Result<(), RuntimeError> {
+        if self.bindings.contains(&name.symbol) {
+            return Ok(());
+        }
+        Err(RuntimeError::new(
+            Some(name.clone()),
+            format!("Module '{}' has no binding '{}'.", self.name, name.lexeme),
+        ))
+    }
+
+    /// The names declared by the top-level `statements` of a module.
+    pub fn declared(statements: &[Stmt]) -> HashSet<Symbol> {
+        statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Stmt::Var(name, ..) | Stmt::Import(_, name) => Some(name.symbol),
+                Stmt::Function(declaration) => declaration.name.as_ref().map(|name| name.symbol),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Module({}, {:?})", self.name, self.path)
+    }
+}
+
+impl PartialEq for Module {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.environment, &other.environment)
+    }
+}
+
+/// Every module that was imported so far, keyed by its canonical path, so a
+/// file only runs once no matter how many times it is imported.
+#[derive(Default)]
+pub struct ModuleCache {
+    loaded: HashMap<PathBuf, Module>,
+    /// Files that are currently executing, outermost first.
+    loading: Vec<PathBuf>,
+}
+
+impl ModuleCache {
+    pub fn get(&self, path: &Path) -> Option<Module> {
+        self.loaded.get(path).cloned()
+    }
+
+    pub fn insert(&mut self, path: PathBuf, module: Module) {
+        self.loaded.insert(path, module);
+    }
+
+    /// Marks `path` as executing. Fails with a printable description of the
+    /// import chain if `path` is already being executed further up.
+    pub fn enter(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|p| p == path) {
+            let chain = self.loading[start..]
+                .iter()
+                .chain(std::iter::once(&path.to_path_buf()))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(chain);
+        }
+        self.loading.push(path.to_path_buf());
+        Ok(())
+    }
+
+    pub fn exit(&mut self) {
+        self.loading.pop();
+    }
+
+    /// Whether an imported module is executing, rather than the main
+    /// script.
+    pub fn importing(&self) -> bool {
+        self.loading.len() > 1
+    }
+
+    /// Directory that relative import paths are resolved against: the one
+    /// containing the file that is currently executing, or the working
+    /// directory for the REPL.
+    pub fn current_dir(&self) -> PathBuf {
+        self.loading
+            .last()
+            .and_then(|p| p.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+}
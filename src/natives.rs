@@ -0,0 +1,416 @@
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+use crate::{
+    callable::NativeFunction,
+    environment::Environment,
+    interpreter::{Interpreter, RuntimeError, LIST_ELEMENT},
+    map::{LoxMap, MapKey},
+    symbol::Symbol,
+    token::{LiteralVal, Token},
+    Lox,
+};
+
+/// Every built-in function.
+pub fn all() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("len", (1, 1), len),
+        NativeFunction::new("push", (2, 2), push),
+        NativeFunction::new("pop", (1, 1), pop),
+        NativeFunction::new("insert", (3, 3), insert),
+        NativeFunction::new("remove", (2, 2), remove),
+        NativeFunction::new("slice", (2, 3), slice),
+        NativeFunction::new("sort", (1, 2), sort),
+        NativeFunction::new("has", (2, 2), has),
+        NativeFunction::new("keys", (1, 1), keys),
+        NativeFunction::new("values", (1, 1), values),
+        NativeFunction::new("readFile", (1, 1), read_file),
+        NativeFunction::new("writeFile", (2, 2), write_file),
+        NativeFunction::new("getEnv", (1, 1), get_env),
+    ]
+}
+
+pub fn define_all(environment: &mut Environment) {
+    for native in all() {
+        environment.define(Symbol::intern(native.name), LiteralVal::Native(native));
+    }
+}
+
+type ListRef = Rc<RefCell<Vec<LiteralVal>>>;
+type MapRef = Rc<RefCell<LoxMap>>;
+
+fn list_operand(paren: &Token, value: &LiteralVal, name: &str) -> Result<ListRef, RuntimeError> {
+    match value {
+        LiteralVal::List(list) => Ok(Rc::clone(list)),
+        _ => Err(RuntimeError::new(
+            Some(paren.clone()),
+            format!("First argument to '{name}' must be a list."),
+        )),
+    }
+}
+
+fn map_operand(paren: &Token, value: &LiteralVal, name: &str) -> Result<MapRef, RuntimeError> {
+    match value {
+        LiteralVal::Map(map) => Ok(Rc::clone(map)),
+        _ => Err(RuntimeError::new(
+            Some(paren.clone()),
+            format!("First argument to '{name}' must be a map."),
+        )),
+    }
+}
+
+fn string_operand(
+    paren: &Token,
+    value: &LiteralVal,
+    position: &str,
+    name: &str,
+) -> Result<String, RuntimeError> {
+    match value {
+        LiteralVal::Str(s) => Ok(s.clone()),
+        _ => Err(RuntimeError::new(
+            Some(paren.clone()),
+            format!("{position} argument to '{name}' must be a string."),
+        )),
+    }
+}
+
+/// Fails unless the built-in `name` was granted the capability it needs.
+fn require(granted: bool, paren: &Token, name: &str, capability: &str) -> Result<(), RuntimeError> {
+    if granted {
+        return Ok(());
+    }
+    Err(RuntimeError::new(
+        Some(paren.clone()),
+        format!("'{name}' needs the {capability} capability."),
+    ))
+}
+
+/// A list of `values`, counted towards the allocation limit.
+fn allocate_list(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    values: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    interpreter.allocate(Some(paren), values.len() * LIST_ELEMENT)?;
+    Ok(new_list(values))
+}
+
+fn new_list(values: Vec<LiteralVal>) -> LiteralVal {
+    LiteralVal::List(Rc::new(RefCell::new(values)))
+}
+
+fn len(
+    _: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let len = match &arguments[0] {
+        LiteralVal::List(list) => list.borrow().len(),
+        LiteralVal::Map(map) => map.borrow().len(),
+        LiteralVal::Str(s) => s.chars().count(),
+        _ => {
+            return Err(RuntimeError::new(
+                Some(paren.clone()),
+                "Argument to 'len' must be a list, a map or a string.".to_string(),
+            ))
+        }
+    };
+    Ok(LiteralVal::Number(len as f32))
+}
+
+fn push(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    mut arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let value = arguments.pop().unwrap();
+    let list = list_operand(paren, &arguments[0], "push")?;
+    interpreter.allocate(Some(paren), LIST_ELEMENT)?;
+    list.borrow_mut().push(value);
+    Ok(LiteralVal::Nil)
+}
+
+fn pop(
+    _: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    list_operand(paren, &arguments[0], "pop")?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| {
+            RuntimeError::new(
+                Some(paren.clone()),
+                "Can't pop from an empty list.".to_string(),
+            )
+        })
+}
+
+fn insert(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    mut arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let value = arguments.pop().unwrap();
+    let list = list_operand(paren, &arguments[0], "insert")?;
+    let len = list.borrow().len();
+    // Inserting right after the last element is allowed.
+    let index = arguments[1].index_operand(paren, len + 1)?;
+    interpreter.allocate(Some(paren), LIST_ELEMENT)?;
+    list.borrow_mut().insert(index, value);
+    Ok(LiteralVal::Nil)
+}
+
+fn remove(
+    _: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    if let LiteralVal::Map(map) = &arguments[0] {
+        let key = MapKey::new(&arguments[1], paren)?;
+        return map.borrow_mut().remove(&key).ok_or_else(|| {
+            RuntimeError::new(Some(paren.clone()), format!("Key {key} not found."))
+        });
+    }
+
+    let list = list_operand(paren, &arguments[0], "remove")?;
+    let len = list.borrow().len();
+    let index = arguments[1].index_operand(paren, len)?;
+    let value = list.borrow_mut().remove(index);
+    Ok(value)
+}
+
+fn slice(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let list = list_operand(paren, &arguments[0], "slice")?;
+    let list = list.borrow();
+    let start = arguments[1].index_operand(paren, list.len() + 1)?;
+    let end = match arguments.get(2) {
+        Some(end) => end.index_operand(paren, list.len() + 1)?,
+        None => list.len(),
+    };
+    if start > end {
+        return Err(RuntimeError::new(
+            Some(paren.clone()),
+            format!("Slice start {start} is after its end {end}."),
+        ));
+    }
+    let sliced = list[start..end].to_vec();
+    drop(list);
+    allocate_list(interpreter, paren, sliced)
+}
+
+/// Sorts the indices `0..len` with `compare`, which is called with two of
+/// them. Unlike `sort_by`, this doesn't panic when `compare` isn't a total
+/// order, which a comparator written in Lox needn't be; the order is then
+/// unspecified. The sort is stable and stops at the first error.
+fn merge_sort(
+    len: usize,
+    mut compare: impl FnMut(usize, usize) -> Result<Ordering, RuntimeError>,
+) -> Result<Vec<usize>, RuntimeError> {
+    let mut sorted: Vec<usize> = (0..len).collect();
+    let mut width = 1;
+    while width < len {
+        let mut merged = Vec::with_capacity(len);
+        for start in (0..len).step_by(2 * width) {
+            let middle = (start + width).min(len);
+            let end = (start + 2 * width).min(len);
+            let (mut left, mut right) = (start, middle);
+            while left < middle && right < end {
+                if compare(sorted[left], sorted[right])? == Ordering::Greater {
+                    merged.push(sorted[right]);
+                    right += 1;
+                } else {
+                    merged.push(sorted[left]);
+                    left += 1;
+                }
+            }
+            merged.extend_from_slice(&sorted[left..middle]);
+            merged.extend_from_slice(&sorted[right..end]);
+        }
+        sorted = merged;
+        width *= 2;
+    }
+    Ok(sorted)
+}
+
+/// Sorts in place. The optional comparator is called with two elements and
+/// returns a negative number, zero or a positive number.
+fn sort(
+    interpreter: &mut Interpreter,
+    lox: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let list = list_operand(paren, &arguments[0], "sort")?;
+    if let Some(comparator) = arguments.get(1) {
+        // The comparator may look at the list, so sort a copy and store it
+        // back afterwards instead of holding the borrow during the calls.
+        let elements = list.borrow().clone();
+        let order = merge_sort(elements.len(), |a, b| {
+            let order = interpreter.call(
+                lox,
+                comparator.clone(),
+                paren,
+                vec![elements[a].clone(), elements[b].clone()],
+            )?;
+            let order = order.number_operand(paren)?;
+            Ok(order.partial_cmp(&0.).unwrap_or(Ordering::Equal))
+        })?;
+        *list.borrow_mut() = order.into_iter().map(|i| elements[i].clone()).collect();
+        return Ok(LiteralVal::Nil);
+    }
+
+    let mut list = list.borrow_mut();
+
+    let all_numbers = list
+        .iter()
+        .all(|v| matches!(v, LiteralVal::Number(_) | LiteralVal::NaN));
+    let all_strings = list.iter().all(|v| matches!(v, LiteralVal::Str(_)));
+    if !all_numbers && !all_strings {
+        return Err(RuntimeError::new(
+            Some(paren.clone()),
+            "Can only sort a list of numbers or a list of strings.".to_string(),
+        ));
+    }
+
+    list.sort_by(|a, b| match (a, b) {
+        (LiteralVal::Str(a), LiteralVal::Str(b)) => a.cmp(b),
+        _ => {
+            let as_number = |v: &LiteralVal| match v {
+                LiteralVal::Number(n) => *n,
+                _ => f32::NAN,
+            };
+            as_number(a)
+                .partial_cmp(&as_number(b))
+                // NaN sorts after every number.
+                .unwrap_or_else(|| as_number(a).is_nan().cmp(&as_number(b).is_nan()))
+        }
+    });
+    Ok(LiteralVal::Nil)
+}
+
+fn has(
+    _: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let map = map_operand(paren, &arguments[0], "has")?;
+    let key = MapKey::new(&arguments[1], paren)?;
+    let has = map.borrow().contains_key(&key);
+    Ok(LiteralVal::Boolean(has))
+}
+
+fn keys(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let map = map_operand(paren, &arguments[0], "keys")?;
+    let keys = map.borrow().iter().map(|(key, _)| key.to_value()).collect();
+    allocate_list(interpreter, paren, keys)
+}
+
+fn values(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    let map = map_operand(paren, &arguments[0], "values")?;
+    let values = map
+        .borrow()
+        .iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    allocate_list(interpreter, paren, values)
+}
+
+/// The contents of a file, relative to the running script like imports.
+fn read_file(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    require(
+        interpreter.capabilities.filesystem,
+        paren,
+        "readFile",
+        "filesystem",
+    )?;
+    let path = string_operand(paren, &arguments[0], "First", "readFile")?;
+    let full = interpreter.modules.current_dir().join(&path);
+    let error = |e: std::io::Error| {
+        RuntimeError::new(
+            Some(paren.clone()),
+            format!("Could not read '{path}': {e}."),
+        )
+    };
+    // Checked before reading, so a file over the limit is never loaded.
+    let size = std::fs::metadata(&full).map_err(error)?.len();
+    let size = usize::try_from(size).unwrap_or(usize::MAX);
+    interpreter.allocate(Some(paren), size)?;
+    let contents = std::fs::read_to_string(full).map_err(error)?;
+    // The file may have grown since.
+    interpreter.allocate(Some(paren), contents.len().saturating_sub(size))?;
+    Ok(LiteralVal::Str(contents))
+}
+
+/// Replaces the contents of a file, relative to the running script.
+fn write_file(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    require(
+        interpreter.capabilities.filesystem,
+        paren,
+        "writeFile",
+        "filesystem",
+    )?;
+    let path = string_operand(paren, &arguments[0], "First", "writeFile")?;
+    let contents = string_operand(paren, &arguments[1], "Second", "writeFile")?;
+    let full = interpreter.modules.current_dir().join(&path);
+    std::fs::write(full, contents).map_err(|e| {
+        RuntimeError::new(
+            Some(paren.clone()),
+            format!("Could not write '{path}': {e}."),
+        )
+    })?;
+    Ok(LiteralVal::Nil)
+}
+
+/// The value of an environment variable, or nil if it isn't set.
+fn get_env(
+    interpreter: &mut Interpreter,
+    _: &mut Lox,
+    paren: &Token,
+    arguments: Vec<LiteralVal>,
+) -> Result<LiteralVal, RuntimeError> {
+    require(
+        interpreter.capabilities.environment,
+        paren,
+        "getEnv",
+        "environment",
+    )?;
+    let name = string_operand(paren, &arguments[0], "First", "getEnv")?;
+    match std::env::var(&name) {
+        Ok(value) => {
+            interpreter.allocate(Some(paren), value.len())?;
+            Ok(LiteralVal::Str(value))
+        }
+        Err(_) => Ok(LiteralVal::Nil),
+    }
+}
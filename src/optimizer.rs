@@ -0,0 +1,306 @@
+use std::rc::Rc;
+
+use crate::{
+    expr::Expr,
+    interpreter::{Interpreter, STACK_RED_ZONE, STACK_SEGMENT},
+    stmt::{FunctionDecl, Stmt},
+    token::{LiteralVal, TokenType},
+};
+
+/// Rewrites the AST before it is run: folds operators whose operands are
+/// literals, picks the live branch of `if` and `?:` with a constant
+/// condition and drops `!!` where only truthiness matters. An operator that
+/// would fail, like `1 / 0`, is left alone so the error is still raised at
+/// runtime at the same token.
+pub struct Optimizer {
+    /// Used to apply operators, so folding can't disagree with evaluation.
+    interpreter: Interpreter,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    pub fn optimize(&self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        statements
+            .into_iter()
+            .filter_map(|statement| self.statement(statement))
+            .collect()
+    }
+
+    /// The optimized statement, or `None` if it can never do anything.
+    fn statement(&self, stmt: Stmt) -> Option<Stmt> {
+        let stmt = match stmt {
+            Stmt::Block(statements) => Stmt::Block(self.optimize(statements)),
+            Stmt::Expression(expr) => Stmt::Expression(self.expression(expr)),
+            Stmt::Function(declaration) => Stmt::Function(self.function(&declaration)),
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.condition(condition);
+                if let Expr::Literal(value) = &condition {
+                    let branch = if self.interpreter.is_truthy(value) {
+                        Some(then_branch)
+                    } else {
+                        else_branch
+                    };
+                    return self.statement(*branch?);
+                }
+                Stmt::If {
+                    keyword,
+                    condition,
+                    then_branch: Box::new(self.branch(*then_branch)),
+                    else_branch: else_branch
+                        .and_then(|branch| self.statement(*branch))
+                        .map(Box::new),
+                }
+            }
+            Stmt::Print(keyword, expr) => Stmt::Print(keyword, self.expression(expr)),
+            Stmt::Return(keyword, value) => {
+                Stmt::Return(keyword, value.map(|value| self.expression(value)))
+            }
+            Stmt::Throw(keyword, value) => Stmt::Throw(keyword, self.expression(value)),
+            Stmt::Try {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => Stmt::Try {
+                keyword,
+                body: self.optimize(body),
+                catch: catch.map(|(name, handler)| (name, self.optimize(handler))),
+                finally: finally.map(|finally| self.optimize(finally)),
+            },
+            Stmt::Var(name, annotation, initializer) => Stmt::Var(
+                name,
+                annotation,
+                initializer.map(|initializer| self.expression(initializer)),
+            ),
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+                increment,
+            } => {
+                let condition = self.condition(condition);
+                if let Expr::Literal(value) = &condition {
+                    if !self.interpreter.is_truthy(value) {
+                        return None;
+                    }
+                }
+                Stmt::While {
+                    keyword,
+                    condition,
+                    body: Box::new(self.branch(*body)),
+                    increment: increment.map(|increment| self.expression(increment)),
+                }
+            }
+            stmt @ (Stmt::Break(_) | Stmt::Continue(_) | Stmt::Import(..)) => stmt,
+        };
+        Some(stmt)
+    }
+
+    /// A statement that has to stay, even if it does nothing.
+    fn branch(&self, stmt: Stmt) -> Stmt {
+        self.statement(stmt).unwrap_or_else(|| Stmt::Block(vec![]))
+    }
+
+    fn function(&self, declaration: &FunctionDecl) -> Rc<FunctionDecl> {
+        Rc::new(FunctionDecl {
+            name: declaration.name.clone(),
+            params: declaration.params.clone(),
+            param_types: declaration.param_types.clone(),
+            return_type: declaration.return_type.clone(),
+            body: self.optimize(declaration.body.clone()),
+        })
+    }
+
+    /// An expression whose value is only used for its truthiness, so `!!x`
+    /// can become `x`.
+    fn condition(&self, expr: Expr) -> Expr {
+        let mut expr = self.expression(expr);
+        while let Expr::Unary { operator, right } = &expr {
+            let Expr::Unary {
+                operator: inner,
+                right: operand,
+            } = right.as_ref()
+            else {
+                break;
+            };
+            if operator.token_type != TokenType::Bang || inner.token_type != TokenType::Bang {
+                break;
+            }
+            expr = operand.as_ref().clone();
+        }
+        expr
+    }
+
+    /// Grows the stack as needed, like `Interpreter::evaluate`.
+    fn expression(&self, expr: Expr) -> Expr {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || self.fold(expr))
+    }
+
+    fn fold(&self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Assign(name, value, slot) => Expr::Assign(name, self.boxed(*value), slot),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.expression(*left);
+                let right = self.expression(*right);
+                match (&left, &right) {
+                    // Literals have no side effects, so only the right
+                    // operand of a comma matters.
+                    (Expr::Literal(_), _) if operator.token_type == TokenType::Comma => right,
+                    (Expr::Literal(l), Expr::Literal(r)) => {
+                        match self.interpreter.binary(&operator, l.clone(), r.clone()) {
+                            Ok(value) => literal(value),
+                            Err(_) => Expr::Binary {
+                                left: Box::new(left),
+                                operator,
+                                right: Box::new(right),
+                            },
+                        }
+                    }
+                    _ => Expr::Binary {
+                        left: Box::new(left),
+                        operator,
+                        right: Box::new(right),
+                    },
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => Expr::Call {
+                callee: self.boxed(*callee),
+                paren,
+                arguments: self.expressions(arguments),
+            },
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.condition(*condition);
+                if let Expr::Literal(value) = &condition {
+                    return if self.interpreter.is_truthy(value) {
+                        self.expression(*then_branch)
+                    } else {
+                        self.expression(*else_branch)
+                    };
+                }
+                Expr::Conditional {
+                    condition: Box::new(condition),
+                    then_branch: self.boxed(*then_branch),
+                    else_branch: self.boxed(*else_branch),
+                }
+            }
+            Expr::Compound {
+                target,
+                operator,
+                value,
+                postfix,
+            } => Expr::Compound {
+                target: self.boxed(*target),
+                operator,
+                value: self.boxed(*value),
+                postfix,
+            },
+            Expr::Get(object, name) => Expr::Get(self.boxed(*object), name),
+            Expr::Grouping(expr) => match self.expression(*expr) {
+                expr @ Expr::Literal(_) => expr,
+                expr => Expr::Grouping(Box::new(expr)),
+            },
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => Expr::Index {
+                object: self.boxed(*object),
+                bracket,
+                index: self.boxed(*index),
+            },
+            Expr::IndexSet {
+                object,
+                bracket,
+                index,
+                value,
+            } => Expr::IndexSet {
+                object: self.boxed(*object),
+                bracket,
+                index: self.boxed(*index),
+                value: self.boxed(*value),
+            },
+            Expr::Lambda(declaration) => Expr::Lambda(self.function(&declaration)),
+            Expr::List(elements) => Expr::List(self.expressions(elements)),
+            Expr::Map(brace, entries) => Expr::Map(
+                brace,
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (self.expression(key), self.expression(value)))
+                    .collect(),
+            ),
+            Expr::Set(object, name, value) => {
+                Expr::Set(self.boxed(*object), name, self.boxed(*value))
+            }
+            Expr::Unary { operator, right } => {
+                let right = if operator.token_type == TokenType::Bang {
+                    self.condition(*right)
+                } else {
+                    self.expression(*right)
+                };
+                match &right {
+                    Expr::Literal(value) => {
+                        match self.interpreter.unary(&operator, value.clone()) {
+                            Ok(value) => literal(value),
+                            Err(_) => Expr::Unary {
+                                operator,
+                                right: Box::new(right),
+                            },
+                        }
+                    }
+                    _ => Expr::Unary {
+                        operator,
+                        right: Box::new(right),
+                    },
+                }
+            }
+            expr @ (Expr::Literal(_) | Expr::Variable(..)) => expr,
+        }
+    }
+
+    fn boxed(&self, expr: Expr) -> Box<Expr> {
+        Box::new(self.expression(expr))
+    }
+
+    fn expressions(&self, exprs: Vec<Expr>) -> Vec<Expr> {
+        exprs
+            .into_iter()
+            .map(|expr| self.expression(expr))
+            .collect()
+    }
+}
+
+/// Same normalisation `Interpreter::evaluate` applies to its results.
+fn literal(value: LiteralVal) -> Expr {
+    match value {
+        LiteralVal::Number(n) if n.is_nan() => Expr::Literal(LiteralVal::NaN),
+        value => Expr::Literal(value),
+    }
+}
@@ -1,6 +1,10 @@
+use std::{path::Path, rc::Rc};
+
 use crate::{
-    expr::Expr,
-    stmt::Stmt,
+    expr::{Expr, Slot},
+    interpreter::{STACK_RED_ZONE, STACK_SEGMENT},
+    stmt::{FunctionDecl, Stmt},
+    symbol::Symbol,
     token::{LiteralVal, Token, TokenType},
     Lox,
 };
@@ -10,14 +14,42 @@ use LiteralVal::Nil;
 #[derive(Debug)]
 pub struct ParserError;
 
+/// How deep statements and expressions may nest. Every pass after the
+/// parser walks the tree recursively too, so this keeps all of them well
+/// within the stack of a thread, even in debug builds.
+const MAX_NESTING: usize = 200;
+
+const TOO_DEEP: &str = "Expression nested too deeply.";
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Number of loops around the statement being parsed, used to reject
+    /// `break` and `continue` outside of one. Reset inside function bodies.
+    loop_depth: usize,
+    /// Number of functions around the statement being parsed, used to
+    /// reject `return` at the top level.
+    function_depth: usize,
+    /// Number of statements and expressions around the one being parsed.
+    /// Operators that chain, like `a + b + c` or `f()()`, count one level
+    /// for each link, as every link nests the ones before it.
+    nesting: usize,
+    /// Set once something nested too deeply. The rest of the input is
+    /// skipped, and every construct it was nested in would otherwise
+    /// report its own missing closing token.
+    too_deep: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            loop_depth: 0,
+            function_depth: 0,
+            nesting: 0,
+            too_deep: false,
+        }
     }
 
     pub fn parse(&mut self, lox: &mut Lox) -> Vec<Stmt> {
@@ -35,28 +67,100 @@ impl Parser {
 
     fn expression(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
         // self.equality(lox)
-        self.assignment(lox)
+        self.comma(lox)
     }
 
     fn declaration(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let nesting = self.nesting;
         let res = if self.match_types(&[TokenType::Var]) {
             self.var_declaration(lox)
+        } else if self.match_types(&[TokenType::Import]) {
+            self.import_declaration(lox)
+        } else if self.check(&TokenType::Fun) && self.check_next(&TokenType::Identifier) {
+            // `fun (` starts a lambda, which is parsed as an expression.
+            self.advance();
+            self.function_declaration(lox)
         } else {
             self.statement(lox)
         };
 
         if res.is_err() {
-            // self.synchronize(lox)
+            // Errors return early, before the levels they entered are left.
+            self.nesting = nesting;
+            self.synchronize();
         }
 
         res
     }
 
+    /// Skips tokens after a syntax error until the start of what is probably
+    /// the next statement, so one mistake doesn't cause a cascade of errors.
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+
+            match self.peek().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::Import
+                | TokenType::Break
+                | TokenType::Continue => return,
+                _ => (),
+            }
+
+            self.advance();
+        }
+    }
+
     fn statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        self.nested(lox, "Statement nested too deeply.", Self::statement_body)
+    }
+
+    fn statement_body(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        if self.match_types(&[TokenType::For]) {
+            return self.for_statement(lox);
+        }
+
+        if self.match_types(&[TokenType::If]) {
+            return self.if_statement(lox);
+        }
+
         if self.match_types(&[TokenType::Print]) {
             return self.print_statement(lox);
         }
 
+        if self.match_types(&[TokenType::While]) {
+            return self.while_statement(lox);
+        }
+
+        if self.match_types(&[TokenType::Break, TokenType::Continue]) {
+            return self.jump_statement(lox);
+        }
+
+        if self.match_types(&[TokenType::Return]) {
+            return self.return_statement(lox);
+        }
+
+        if self.match_types(&[TokenType::Throw]) {
+            return self.throw_statement(lox);
+        }
+
+        if self.match_types(&[TokenType::Try]) {
+            return self.try_statement(lox);
+        }
+
         if self.match_types(&[TokenType::LeftBrace]) {
             return Ok(Stmt::Block(self.block(lox)?));
         }
@@ -64,19 +168,277 @@ impl Parser {
         self.expression_statement(lox)
     }
 
+    fn for_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        self.consume(lox, &TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.match_types(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_types(&[TokenType::Var]) {
+            Some(self.var_declaration(lox)?)
+        } else {
+            Some(self.expression_statement(lox)?)
+        };
+
+        let condition = if self.check(&TokenType::Semicolon) {
+            Expr::Literal(LiteralVal::Boolean(true))
+        } else {
+            self.expression(lox)?
+        };
+        self.consume(
+            lox,
+            &TokenType::Semicolon,
+            "Expect ';' after loop condition.",
+        )?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression(lox)?)
+        };
+        self.consume(lox, &TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.loop_body(lox)?;
+        let while_loop = Stmt::While {
+            keyword,
+            condition,
+            body: Box::new(body),
+            increment,
+        };
+
+        Ok(match initializer {
+            Some(initializer) => Stmt::Block(vec![initializer, while_loop]),
+            None => while_loop,
+        })
+    }
+
+    fn if_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        self.consume(lox, &TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression(lox)?;
+        self.consume(
+            lox,
+            &TokenType::RightParen,
+            "Expect ')' after if condition.",
+        )?;
+
+        let then_branch = Box::new(self.statement(lox)?);
+        let else_branch = if self.match_types(&[TokenType::Else]) {
+            Some(Box::new(self.statement(lox)?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn while_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        self.consume(lox, &TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression(lox)?;
+        self.consume(lox, &TokenType::RightParen, "Expect ')' after condition.")?;
+
+        let body = self.loop_body(lox)?;
+
+        Ok(Stmt::While {
+            keyword,
+            condition,
+            body: Box::new(body),
+            increment: None,
+        })
+    }
+
+    fn loop_body(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        self.loop_depth += 1;
+        let body = self.statement(lox);
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn jump_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            self.error(
+                lox,
+                &keyword,
+                &format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            );
+        }
+        self.consume(
+            lox,
+            &TokenType::Semicolon,
+            &format!("Expect ';' after '{}'.", keyword.lexeme),
+        )?;
+
+        Ok(match keyword.token_type {
+            TokenType::Break => Stmt::Break(keyword),
+            _ => Stmt::Continue(keyword),
+        })
+    }
+
+    fn return_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            self.error(lox, &keyword, "Can't return from top-level code.");
+        }
+
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression(lox)?)
+        };
+        self.consume(lox, &TokenType::Semicolon, "Expect ';' after return value.")?;
+
+        Ok(Stmt::Return(keyword, value))
+    }
+
+    fn throw_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        let value = self.expression(lox)?;
+        self.consume(lox, &TokenType::Semicolon, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw(keyword, value))
+    }
+
+    fn try_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let keyword = self.previous().clone();
+        self.consume(lox, &TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+        let body = self.block(lox)?;
+
+        let catch = if self.match_types(&[TokenType::Catch]) {
+            self.consume(lox, &TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self
+                .consume(lox, &TokenType::Identifier, "Expect variable name.")?
+                .clone();
+            self.consume(
+                lox,
+                &TokenType::RightParen,
+                "Expect ')' after variable name.",
+            )?;
+            self.consume(lox, &TokenType::LeftBrace, "Expect '{' before catch body.")?;
+            Some((name, self.block(lox)?))
+        } else {
+            None
+        };
+
+        let finally = if self.match_types(&[TokenType::Finally]) {
+            self.consume(lox, &TokenType::LeftBrace, "Expect '{' after 'finally'.")?;
+            Some(self.block(lox)?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            self.error(
+                lox,
+                self.peek(),
+                "Expect 'catch' or 'finally' after try block.",
+            );
+        }
+
+        Ok(Stmt::Try {
+            keyword,
+            body,
+            catch,
+            finally,
+        })
+    }
+
     fn print_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
-        let value = self.expression(lox);
+        let keyword = self.previous().clone();
+        let value = self.expression(lox)?;
         self.consume(lox, &TokenType::Semicolon, "Expect ';' after value.")?;
-        match value {
-            Ok(v) => Ok(Stmt::Print(v)),
-            Err(e) => Err(e),
+        Ok(Stmt::Print(keyword, value))
+    }
+
+    fn function_declaration(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let name = self
+            .consume(lox, &TokenType::Identifier, "Expect function name.")?
+            .clone();
+        Ok(Stmt::Function(self.function(lox, Some(name))?))
+    }
+
+    /// Parameter list and body shared by declarations and lambdas.
+    fn function(
+        &mut self,
+        lox: &mut Lox,
+        name: Option<Token>,
+    ) -> Result<Rc<FunctionDecl>, ParserError> {
+        let kind = if name.is_some() {
+            "function name"
+        } else {
+            "'fun'"
+        };
+        self.consume(
+            lox,
+            &TokenType::LeftParen,
+            &format!("Expect '(' after {kind}."),
+        )?;
+        let mut params = vec![];
+        let mut param_types = vec![];
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    self.error(lox, self.peek(), "Can't have more than 255 parameters.");
+                }
+                params.push(
+                    self.consume(lox, &TokenType::Identifier, "Expect parameter name.")?
+                        .clone(),
+                );
+                param_types.push(self.type_annotation(lox)?);
+                if !self.match_types(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
+        self.consume(lox, &TokenType::RightParen, "Expect ')' after parameters.")?;
+        let return_type = self.type_annotation(lox)?;
+        self.consume(
+            lox,
+            &TokenType::LeftBrace,
+            "Expect '{' before function body.",
+        )?;
+
+        // Loops outside the function don't make `break` valid inside it.
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let body = self.block(lox);
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+
+        Ok(Rc::new(FunctionDecl {
+            name,
+            params,
+            param_types,
+            return_type,
+            body: body?,
+        }))
+    }
+
+    /// An optional `: type` after a variable, parameter or parameter list.
+    /// `nil` and `fun` are keywords, but also type names.
+    fn type_annotation(&mut self, lox: &mut Lox) -> Result<Option<Token>, ParserError> {
+        if !self.match_types(&[TokenType::Colon]) {
+            return Ok(None);
+        }
+
+        if self.match_types(&[TokenType::Identifier, TokenType::Nil, TokenType::Fun]) {
+            return Ok(Some(self.previous().clone()));
+        }
+
+        self.error(lox, self.peek(), "Expect type after ':'.");
+        Err(ParserError)
     }
 
     fn var_declaration(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
         let name = self
             .consume(lox, &TokenType::Identifier, "Expect variable name.")?
             .clone();
+        let var_type = self.type_annotation(lox)?;
 
         let mut initializer = None;
         if self.match_types(&[TokenType::Equal]) {
@@ -89,23 +451,77 @@ impl Parser {
             "Expect ';' after variable declaration.",
         )?;
 
-        Ok(Stmt::Var(name, initializer))
+        Ok(Stmt::Var(name, var_type, initializer))
+    }
+
+    fn import_declaration(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
+        let path = self
+            .consume(
+                lox,
+                &TokenType::String,
+                "Expect module path after 'import'.",
+            )?
+            .clone();
+
+        let name = if self.check(&TokenType::Identifier) && self.peek().lexeme == "as" {
+            self.advance();
+            self.consume(
+                lox,
+                &TokenType::Identifier,
+                "Expect module name after 'as'.",
+            )?
+            .clone()
+        } else {
+            let stem = match &path.literal {
+                LiteralVal::Str(p) => Path::new(p)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                _ => unreachable!(),
+            };
+            let mut chars = stem.chars();
+            let is_identifier = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_identifier {
+                self.error(
+                    lox,
+                    &path,
+                    "Module file name is not a valid identifier; name it with 'as'.",
+                );
+                return Err(ParserError);
+            }
+            Token {
+                token_type: TokenType::Identifier,
+                symbol: Symbol::intern(&stem),
+                lexeme: stem,
+                literal: Nil,
+                line: path.line,
+                column: path.column,
+            }
+        };
+
+        self.consume(lox, &TokenType::Semicolon, "Expect ';' after import.")?;
+
+        Ok(Stmt::Import(path, name))
     }
 
     fn expression_statement(&mut self, lox: &mut Lox) -> Result<Stmt, ParserError> {
-        let value = self.expression(lox);
+        let value = self.expression(lox)?;
         self.consume(lox, &TokenType::Semicolon, "Expect ';' after expression.")?;
-        match value {
-            Ok(v) => Ok(Stmt::Expression(v)),
-            Err(e) => Err(e),
-        }
+        Ok(Stmt::Expression(value))
     }
 
     fn block(&mut self, lox: &mut Lox) -> Result<Vec<Stmt>, ParserError> {
         let mut res = vec![];
 
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            res.push(self.declaration(lox)?);
+            // Errors were already reported and skipped by `declaration`.
+            if let Ok(statement) = self.declaration(lox) {
+                res.push(statement);
+            }
         }
 
         self.consume(
@@ -117,17 +533,119 @@ impl Parser {
         Ok(res)
     }
 
+    /// `a, b` evaluates `a`, throws its value away and produces `b`.
+    fn comma(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let mut expr = self.assignment(lox)?;
+
+        let nesting = self.nesting;
+        while self.match_types(&[TokenType::Comma]) {
+            self.enter(lox, TOO_DEEP)?;
+            let operator = self.previous().clone();
+            let right = self.assignment(lox)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        self.nesting = nesting;
+        Ok(expr)
+    }
+
     fn assignment(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
-        let expr = self.equality(lox)?;
+        let expr = self.conditional(lox)?;
 
         if self.match_types(&[TokenType::Equal]) {
             let equals = self.previous().clone();
-            let value = self.assignment(lox)?;
+            let value = self.nested(lox, TOO_DEEP, Self::assignment)?;
 
             match expr {
-                Expr::Variable(name) => return Ok(Expr::Assign(name, Box::new(value))),
+                Expr::Variable(name, _) => {
+                    return Ok(Expr::Assign(name, Box::new(value), Slot::default()))
+                }
+                Expr::Get(object, name) => return Ok(Expr::Set(object, name, Box::new(value))),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    return Ok(Expr::IndexSet {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    })
+                }
                 _ => self.error(lox, &equals, "Invalid assignment target."),
             };
+        } else if self.match_types(&[
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+        ]) {
+            let operator = self.previous().clone();
+            let value = self.nested(lox, TOO_DEEP, Self::assignment)?;
+            return Ok(self.compound(lox, expr, operator, value, false));
+        }
+
+        Ok(expr)
+    }
+
+    /// Builds a compound assignment, reporting an error at `operator` if
+    /// `target` can't be assigned to.
+    fn compound(
+        &mut self,
+        lox: &mut Lox,
+        target: Expr,
+        operator: Token,
+        value: Expr,
+        postfix: bool,
+    ) -> Expr {
+        if !matches!(
+            target,
+            Expr::Variable(..) | Expr::Get(..) | Expr::Index { .. }
+        ) {
+            self.error(lox, &operator, "Invalid assignment target.");
+            return target;
+        }
+
+        let token_type = match operator.token_type {
+            TokenType::PlusEqual | TokenType::PlusPlus => TokenType::Plus,
+            TokenType::MinusEqual | TokenType::MinusMinus => TokenType::Minus,
+            TokenType::StarEqual => TokenType::Star,
+            TokenType::SlashEqual => TokenType::Slash,
+            _ => unreachable!(),
+        };
+
+        Expr::Compound {
+            target: Box::new(target),
+            operator: Token {
+                token_type,
+                ..operator
+            },
+            value: Box::new(value),
+            postfix,
+        }
+    }
+
+    fn conditional(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let expr = self.equality(lox)?;
+
+        if self.match_types(&[TokenType::Question]) {
+            let then_branch = self.expression(lox)?;
+            self.consume(
+                lox,
+                &TokenType::Colon,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_branch = self.nested(lox, TOO_DEEP, Self::conditional)?;
+            return Ok(Expr::Conditional {
+                condition: Box::new(expr),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            });
         }
 
         Ok(expr)
@@ -136,7 +654,9 @@ impl Parser {
     fn equality(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
         let mut expr = self.comparison(lox)?;
 
+        let nesting = self.nesting;
         while self.match_types(&[TokenType::BangEqual, TokenType::EqualEqual]) {
+            self.enter(lox, TOO_DEEP)?;
             let operator = self.previous().clone();
             let right = self.comparison(lox)?;
             expr = Expr::Binary {
@@ -146,18 +666,21 @@ impl Parser {
             };
         }
 
+        self.nesting = nesting;
         Ok(expr)
     }
 
     fn comparison(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
         let mut expr = self.term(lox)?;
 
+        let nesting = self.nesting;
         while self.match_types(&[
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
         ]) {
+            self.enter(lox, TOO_DEEP)?;
             let operator = self.previous().clone();
             let right = self.term(lox)?;
             expr = Expr::Binary {
@@ -167,13 +690,16 @@ impl Parser {
             };
         }
 
+        self.nesting = nesting;
         Ok(expr)
     }
 
     fn term(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
         let mut expr = self.factor(lox)?;
 
+        let nesting = self.nesting;
         while self.match_types(&[TokenType::Plus, TokenType::Minus]) {
+            self.enter(lox, TOO_DEEP)?;
             let operator = self.previous().clone();
             let right = self.factor(lox)?;
             expr = Expr::Binary {
@@ -183,13 +709,100 @@ impl Parser {
             };
         }
 
+        self.nesting = nesting;
         Ok(expr)
     }
 
     fn factor(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let mut expr = self.bit_or(lox)?;
+
+        let nesting = self.nesting;
+        while self.match_types(&[
+            TokenType::Slash,
+            TokenType::Star,
+            TokenType::Percent,
+            TokenType::TildeSlash,
+        ]) {
+            self.enter(lox, TOO_DEEP)?;
+            let operator = self.previous().clone();
+            let right = self.bit_or(lox)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        self.nesting = nesting;
+        Ok(expr)
+    }
+
+    /// The bitwise and shift operators bind tighter than the arithmetic
+    /// ones but looser than unary operators, from `|` up to the shifts, so
+    /// `1 + 2 << 3` is `1 + (2 << 3)` and `a & b * c` is `(a & b) * c`.
+    fn bit_or(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let mut expr = self.bit_xor(lox)?;
+
+        let nesting = self.nesting;
+        while self.match_types(&[TokenType::Pipe]) {
+            self.enter(lox, TOO_DEEP)?;
+            let operator = self.previous().clone();
+            let right = self.bit_xor(lox)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        self.nesting = nesting;
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let mut expr = self.bit_and(lox)?;
+
+        let nesting = self.nesting;
+        while self.match_types(&[TokenType::Caret]) {
+            self.enter(lox, TOO_DEEP)?;
+            let operator = self.previous().clone();
+            let right = self.bit_and(lox)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        self.nesting = nesting;
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let mut expr = self.shift(lox)?;
+
+        let nesting = self.nesting;
+        while self.match_types(&[TokenType::Ampersand]) {
+            self.enter(lox, TOO_DEEP)?;
+            let operator = self.previous().clone();
+            let right = self.shift(lox)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        self.nesting = nesting;
+        Ok(expr)
+    }
+
+    fn shift(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
         let mut expr = self.unary(lox)?;
 
-        while self.match_types(&[TokenType::Slash, TokenType::Star]) {
+        let nesting = self.nesting;
+        while self.match_types(&[TokenType::LessLess, TokenType::GreaterGreater]) {
+            self.enter(lox, TOO_DEEP)?;
             let operator = self.previous().clone();
             let right = self.unary(lox)?;
             expr = Expr::Binary {
@@ -199,11 +812,19 @@ impl Parser {
             };
         }
 
+        self.nesting = nesting;
         Ok(expr)
     }
 
+    /// Every expression nested in another goes through here, except for the
+    /// right-hand sides of assignments and conditionals, so this is where
+    /// their depth is counted.
     fn unary(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
-        if self.match_types(&[TokenType::Bang, TokenType::Minus]) {
+        self.nested(lox, TOO_DEEP, Self::unary_body)
+    }
+
+    fn unary_body(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        if self.match_types(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
             let operator = self.previous().clone();
             let right = self.unary(lox)?;
             return Ok(Expr::Unary {
@@ -212,7 +833,116 @@ impl Parser {
             });
         }
 
-        self.primary(lox)
+        if self.match_types(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().clone();
+            let target = self.unary(lox)?;
+            let one = Expr::Literal(LiteralVal::Number(1.));
+            return Ok(self.compound(lox, target, operator, one, false));
+        }
+
+        self.exponent(lox)
+    }
+
+    /// `**` binds tighter than unary operators and is right-associative, so
+    /// `-2 ** 2` is `-(2 ** 2)` and `2 ** 3 ** 2` is `2 ** (3 ** 2)`.
+    fn exponent(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let expr = self.postfix(lox)?;
+
+        if self.match_types(&[TokenType::StarStar]) {
+            let operator = self.previous().clone();
+            let right = self.unary(lox)?;
+            return Ok(Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn postfix(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let expr = self.call(lox)?;
+
+        if self.match_types(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().clone();
+            let one = Expr::Literal(LiteralVal::Number(1.));
+            return Ok(self.compound(lox, expr, operator, one, true));
+        }
+
+        Ok(expr)
+    }
+
+    fn call(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let mut expr = self.primary(lox)?;
+
+        let nesting = self.nesting;
+        loop {
+            if self.check(&TokenType::LeftParen)
+                || self.check(&TokenType::LeftBracket)
+                || self.check(&TokenType::Dot)
+            {
+                self.enter(lox, TOO_DEEP)?;
+            }
+            if self.match_types(&[TokenType::LeftParen]) {
+                expr = self.finish_call(lox, expr)?;
+            } else if self.match_types(&[TokenType::LeftBracket]) {
+                let index = self.expression(lox)?;
+                let bracket = self
+                    .consume(lox, &TokenType::RightBracket, "Expect ']' after index.")?
+                    .clone();
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
+            } else if self.match_types(&[TokenType::Dot]) {
+                let name = self
+                    .consume(
+                        lox,
+                        &TokenType::Identifier,
+                        "Expect property name after '.'.",
+                    )?
+                    .clone();
+                expr = Expr::Get(Box::new(expr), name);
+            } else {
+                break;
+            }
+        }
+        self.nesting = nesting;
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, lox: &mut Lox, callee: Expr) -> Result<Expr, ParserError> {
+        let arguments = self.arguments(lox, &TokenType::RightParen)?;
+        let paren = self
+            .consume(lox, &TokenType::RightParen, "Expect ')' after arguments.")?
+            .clone();
+
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    /// Comma separated expressions up to, but not including, `closing`.
+    fn arguments(&mut self, lox: &mut Lox, closing: &TokenType) -> Result<Vec<Expr>, ParserError> {
+        let mut arguments = vec![];
+        if !self.check(closing) {
+            loop {
+                if arguments.len() >= 255 {
+                    self.error(lox, self.peek(), "Can't have more than 255 arguments.");
+                }
+                arguments.push(self.assignment(lox)?);
+                if !self.match_types(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        Ok(arguments)
     }
 
     fn primary(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
@@ -231,7 +961,27 @@ impl Parser {
         }
 
         if self.match_types(&[TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous().clone()));
+            return Ok(Expr::Variable(self.previous().clone(), Slot::default()));
+        }
+
+        if self.match_types(&[TokenType::Fun]) {
+            return Ok(Expr::Lambda(self.function(lox, None)?));
+        }
+
+        if self.match_types(&[TokenType::LeftBracket]) {
+            let elements = self.arguments(lox, &TokenType::RightBracket)?;
+            self.consume(
+                lox,
+                &TokenType::RightBracket,
+                "Expect ']' after list elements.",
+            )?;
+            return Ok(Expr::List(elements));
+        }
+
+        // A '{' that starts a statement is a block, so braces only mean a map
+        // literal here, at expression position.
+        if self.match_types(&[TokenType::LeftBrace]) {
+            return self.map(lox);
         }
 
         if self.match_types(&[TokenType::LeftParen]) {
@@ -246,6 +996,25 @@ impl Parser {
         Err(ParserError)
     }
 
+    fn map(&mut self, lox: &mut Lox) -> Result<Expr, ParserError> {
+        let brace = self.previous().clone();
+        let mut entries = vec![];
+        if !self.check(&TokenType::RightBrace) {
+            loop {
+                let key = self.assignment(lox)?;
+                self.consume(lox, &TokenType::Colon, "Expect ':' after map key.")?;
+                let value = self.assignment(lox)?;
+                entries.push((key, value));
+                if !self.match_types(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(lox, &TokenType::RightBrace, "Expect '}' after map entries.")?;
+
+        Ok(Expr::Map(brace, entries))
+    }
+
     fn match_types(&mut self, types: &[TokenType]) -> bool {
         for ty in types {
             if self.check(ty) {
@@ -279,6 +1048,13 @@ impl Parser {
         &self.peek().token_type == t_type
     }
 
+    fn check_next(&self, t_type: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => &token.token_type == t_type,
+            None => false,
+        }
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -297,7 +1073,37 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
+    /// Runs `parse` one level deeper. A level takes a lot of stack in debug
+    /// builds, so the stack grows as needed, as it does for calls.
+    fn nested<T>(
+        &mut self,
+        lox: &mut Lox,
+        message: &str,
+        parse: impl FnOnce(&mut Self, &mut Lox) -> Result<T, ParserError>,
+    ) -> Result<T, ParserError> {
+        self.enter(lox, message)?;
+        let res = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || parse(self, lox));
+        self.nesting -= 1;
+        res
+    }
+
+    /// Goes one level deeper, failing with `message` past `MAX_NESTING`.
+    fn enter(&mut self, lox: &mut Lox, message: &str) -> Result<(), ParserError> {
+        if self.nesting == MAX_NESTING {
+            let token = self.peek().clone();
+            self.error(lox, &token, message);
+            self.too_deep = true;
+            self.current = self.tokens.len() - 1;
+            return Err(ParserError);
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
     fn error(&self, lox: &mut Lox, token: &Token, message: &str) {
+        if self.too_deep {
+            return;
+        }
         lox.error_token(token, message);
     }
 }
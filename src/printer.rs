@@ -0,0 +1,406 @@
+use crate::{
+    expr::Expr,
+    stmt::{FunctionDecl, Stmt},
+    token::{LiteralVal, Token, TokenType},
+};
+
+/// 2¹²⁸, the smallest number literal the scanner turns into infinity.
+const INFINITY: &str = "340282366920938463463374607431768211456";
+
+/// Turns statements back into Lox source. Parsing the output gives the same
+/// statements again, so groupings are printed as the parentheses they came
+/// from and no others are added.
+///
+/// Desugared `for` loops are printed as `for` loops again, and compound
+/// assignments with the lexeme they were written with.
+#[derive(Default)]
+pub struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn print(mut self, statements: &[Stmt]) -> String {
+        for statement in statements {
+            self.statement(statement);
+            self.out.push('\n');
+        }
+        self.out
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.statement_body(stmt);
+    }
+
+    /// A statement without the indentation of its first line.
+    fn statement_body(&mut self, stmt: &Stmt) {
+        if let Some((initializer, condition, body, increment)) = for_loop(stmt) {
+            self.out.push_str("for (");
+            match initializer {
+                Some(initializer) => self.statement_body(initializer),
+                None => self.out.push(';'),
+            }
+            self.out.push(' ');
+            self.expression(condition);
+            self.out.push(';');
+            if let Some(increment) = increment {
+                self.out.push(' ');
+                self.expression(increment);
+            }
+            self.out.push(')');
+            self.branch(body);
+            return;
+        }
+
+        match stmt {
+            Stmt::Block(statements) => self.block(statements),
+            Stmt::Break(_) => self.out.push_str("break;"),
+            Stmt::Continue(_) => self.out.push_str("continue;"),
+            Stmt::Expression(expr) => {
+                self.expression(expr);
+                self.out.push(';');
+            }
+            Stmt::Function(declaration) => self.function(declaration),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.out.push_str("if (");
+                self.expression(condition);
+                self.out.push(')');
+                self.branch(then_branch);
+                if let Some(else_branch) = else_branch {
+                    if is_block(then_branch) {
+                        self.out.push(' ');
+                    } else {
+                        self.out.push('\n');
+                        self.out.push_str(&"    ".repeat(self.indent));
+                    }
+                    self.out.push_str("else");
+                    if let Stmt::If { .. } = else_branch.as_ref() {
+                        self.out.push(' ');
+                        self.statement_body(else_branch);
+                    } else {
+                        self.branch(else_branch);
+                    }
+                }
+            }
+            Stmt::Import(path, name) => {
+                self.out
+                    .push_str(&format!("import {} as {};", path.lexeme, name.lexeme));
+            }
+            Stmt::Print(_, expr) => {
+                self.out.push_str("print ");
+                self.expression(expr);
+                self.out.push(';');
+            }
+            Stmt::Return(_, value) => {
+                self.out.push_str("return");
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expression(value);
+                }
+                self.out.push(';');
+            }
+            Stmt::Throw(_, value) => {
+                self.out.push_str("throw ");
+                self.expression(value);
+                self.out.push(';');
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.out.push_str("try ");
+                self.block(body);
+                if let Some((name, handler)) = catch {
+                    self.out.push_str(&format!(" catch ({}) ", name.lexeme));
+                    self.block(handler);
+                }
+                if let Some(finally) = finally {
+                    self.out.push_str(" finally ");
+                    self.block(finally);
+                }
+            }
+            Stmt::Var(name, var_type, initializer) => {
+                self.out.push_str("var ");
+                self.out.push_str(&name.lexeme);
+                self.annotation(var_type);
+                if let Some(initializer) = initializer {
+                    self.out.push_str(" = ");
+                    self.expression(initializer);
+                }
+                self.out.push(';');
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.out.push_str("while (");
+                self.expression(condition);
+                self.out.push(')');
+                self.branch(body);
+            }
+        }
+    }
+
+    /// The body of an `if`, `else` or loop: blocks go on the same line,
+    /// other statements on the next one, indented.
+    fn branch(&mut self, stmt: &Stmt) {
+        if is_block(stmt) {
+            self.out.push(' ');
+            self.statement_body(stmt);
+        } else {
+            self.out.push('\n');
+            self.indent += 1;
+            self.statement(stmt);
+            self.indent -= 1;
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for statement in statements {
+            self.statement(statement);
+            self.out.push('\n');
+        }
+        self.indent -= 1;
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push('}');
+    }
+
+    /// `fun name(params): type { body }`, without the name for lambdas.
+    fn function(&mut self, declaration: &FunctionDecl) {
+        self.out.push_str("fun");
+        match &declaration.name {
+            Some(name) => {
+                self.out.push(' ');
+                self.out.push_str(&name.lexeme);
+            }
+            None => self.out.push(' '),
+        }
+        self.out.push('(');
+        for (i, (param, param_type)) in declaration
+            .params
+            .iter()
+            .zip(&declaration.param_types)
+            .enumerate()
+        {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(&param.lexeme);
+            self.annotation(param_type);
+        }
+        self.out.push(')');
+        self.annotation(&declaration.return_type);
+        self.out.push(' ');
+        self.block(&declaration.body);
+    }
+
+    fn annotation(&mut self, annotation: &Option<Token>) {
+        if let Some(annotation) = annotation {
+            self.out.push_str(": ");
+            self.out.push_str(&annotation.lexeme);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(name, value, _) => {
+                self.out.push_str(&name.lexeme);
+                self.out.push_str(" = ");
+                self.expression(value);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(left);
+                if operator.token_type != TokenType::Comma {
+                    self.out.push(' ');
+                }
+                self.out.push_str(&operator.lexeme);
+                self.out.push(' ');
+                self.expression(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                self.out.push('(');
+                self.list(arguments);
+                self.out.push(')');
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.out.push_str(" ? ");
+                self.expression(then_branch);
+                self.out.push_str(" : ");
+                self.expression(else_branch);
+            }
+            Expr::Compound {
+                target,
+                operator,
+                value,
+                postfix,
+            } => match operator.lexeme.as_str() {
+                "++" | "--" if *postfix => {
+                    self.expression(target);
+                    self.out.push_str(&operator.lexeme);
+                }
+                "++" | "--" => {
+                    self.out.push_str(&operator.lexeme);
+                    self.expression(target);
+                }
+                _ => {
+                    self.expression(target);
+                    self.out.push(' ');
+                    self.out.push_str(&operator.lexeme);
+                    self.out.push(' ');
+                    self.expression(value);
+                }
+            },
+            Expr::Get(object, name) => {
+                self.expression(object);
+                self.out.push('.');
+                self.out.push_str(&name.lexeme);
+            }
+            Expr::Grouping(expr) => {
+                self.out.push('(');
+                self.expression(expr);
+                self.out.push(')');
+            }
+            Expr::Index { object, index, .. } => {
+                self.expression(object);
+                self.out.push('[');
+                self.expression(index);
+                self.out.push(']');
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.out.push('[');
+                self.expression(index);
+                self.out.push_str("] = ");
+                self.expression(value);
+            }
+            Expr::Lambda(declaration) => self.function(declaration),
+            Expr::List(elements) => {
+                self.out.push('[');
+                self.list(elements);
+                self.out.push(']');
+            }
+            Expr::Literal(value) => self.literal(value),
+            Expr::Map(_, entries) => {
+                self.out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(key);
+                    self.out.push_str(": ");
+                    self.expression(value);
+                }
+                self.out.push('}');
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object);
+                self.out.push('.');
+                self.out.push_str(&name.lexeme);
+                self.out.push_str(" = ");
+                self.expression(value);
+            }
+            Expr::Unary { operator, right } => {
+                self.out.push_str(&operator.lexeme);
+                let start = self.out.len();
+                self.expression(right);
+                // `- -x` must not become `--x`.
+                if self.out[start..].starts_with(&operator.lexeme) {
+                    self.out.insert(start, ' ');
+                }
+            }
+            Expr::Variable(name, _) => self.out.push_str(&name.lexeme),
+        }
+    }
+
+    fn list(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(expr);
+        }
+    }
+
+    /// Negative numbers and NaN only appear after constant folding. They
+    /// have no literal syntax, so they're printed as expressions that
+    /// evaluate to them.
+    fn literal(&mut self, value: &LiteralVal) {
+        match value {
+            LiteralVal::Number(n) if n.is_nan() => {
+                self.out.push_str(&format!("(0 * {INFINITY})"));
+            }
+            LiteralVal::Number(n) if n.is_sign_negative() => {
+                self.out.push_str("(-");
+                self.literal(&LiteralVal::Number(-n));
+                self.out.push(')');
+            }
+            LiteralVal::Number(n) if n.is_infinite() => self.out.push_str(INFINITY),
+            LiteralVal::Number(n) => self.out.push_str(&n.to_string()),
+            LiteralVal::NaN => self.literal(&LiteralVal::Number(f32::NAN)),
+            LiteralVal::Str(s) => self.out.push_str(&format!("\"{s}\"")),
+            LiteralVal::Boolean(b) => self.out.push_str(&b.to_string()),
+            LiteralVal::Nil => self.out.push_str("nil"),
+            _ => unreachable!("runtime values never appear in the AST"),
+        }
+    }
+}
+
+fn is_block(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::Block(_)) && for_loop(stmt).is_none()
+}
+
+/// The initializer, condition, body and increment of a desugared `for`
+/// loop: a `while` with the `for` keyword, alone or in a block after its
+/// initializer.
+fn for_loop(stmt: &Stmt) -> Option<(Option<&Stmt>, &Expr, &Stmt, Option<&Expr>)> {
+    let (initializer, while_loop) = match stmt {
+        Stmt::Block(statements) => match statements.as_slice() {
+            [initializer @ (Stmt::Var(..) | Stmt::Expression(_)), while_loop] => {
+                (Some(initializer), while_loop)
+            }
+            _ => return None,
+        },
+        _ => (None, stmt),
+    };
+    match while_loop {
+        Stmt::While {
+            keyword,
+            condition,
+            body,
+            increment,
+        } if keyword.token_type == TokenType::For => {
+            Some((initializer, condition, body, increment.as_ref()))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    expr::{Expr, Slot},
+    stmt::{FunctionDecl, Stmt},
+    symbol::Symbol,
+    token::Token,
+};
+
+/// Assigns every local variable a slot in its scope and records in each
+/// `Variable` and `Assign` expression how to reach it. This has to mirror
+/// the environments the interpreter creates exactly: one per block, one per
+/// call, holding the parameters and the function body's declarations, and
+/// one per `catch` clause, holding the caught value and the handler's.
+/// Variables that aren't found in any local scope are globals.
+///
+/// Like in the book, a local is only visible to code that comes after its
+/// declaration. Functions are the exception: code inside a function can
+/// also see the local functions declared further down an enclosing block,
+/// so local functions can call each other.
+#[derive(Default)]
+pub struct Resolver {
+    /// Local scopes, innermost last. Top-level code has none.
+    scopes: Vec<Scope>,
+}
+
+#[derive(Default)]
+struct Scope {
+    /// Names of the variables, indexed by slot.
+    variables: Vec<Symbol>,
+    /// Every function declared directly in the scope, with its slot.
+    /// Declarations take slots in order, so this is known before they run.
+    functions: Vec<(Symbol, usize)>,
+    /// Whether this is the scope of a call, holding the parameters.
+    call: bool,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let mut slot = scope.variables.len();
+            for statement in statements {
+                match statement {
+                    Stmt::Function(declaration) => {
+                        let name = declaration.name.as_ref().unwrap().symbol;
+                        scope.functions.push((name, slot));
+                        slot += 1;
+                    }
+                    Stmt::Import(..) | Stmt::Var(..) => slot += 1,
+                    _ => (),
+                }
+            }
+        }
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(statements) => self.statement_block(statements),
+            Stmt::Break(_) | Stmt::Continue(_) => (),
+            Stmt::Expression(expr) | Stmt::Print(_, expr) | Stmt::Throw(_, expr) => {
+                self.expression(expr)
+            }
+            Stmt::Function(declaration) => {
+                if let Some(name) = &declaration.name {
+                    self.declare(name);
+                }
+                self.function(declaration);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Import(_, name) => self.declare(name),
+            Stmt::Return(_, value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.statement_block(body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(Scope::default());
+                    self.declare(name);
+                    self.resolve(handler);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.statement_block(finally);
+                }
+            }
+            Stmt::Var(name, _, initializer) => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(body);
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+            }
+        }
+    }
+
+    fn statement_block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(Scope::default());
+        self.resolve(statements);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(name, value, slot) => {
+                self.expression(value);
+                self.local(name, slot, false);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Compound { target, value, .. } => {
+                self.expression(target);
+                self.expression(value);
+            }
+            Expr::Get(object, _) => self.expression(object),
+            Expr::Grouping(expr) => self.expression(expr),
+            Expr::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+            }
+            Expr::Lambda(declaration) => self.function(declaration),
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expr::Literal(_) => (),
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expr::Set(object, _, value) => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::Variable(name, slot) => self.local(name, slot, true),
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) {
+        self.scopes.push(Scope {
+            call: true,
+            ..Scope::default()
+        });
+        for param in &declaration.params {
+            self.declare(param);
+        }
+        self.resolve(&declaration.body);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.variables.push(name.symbol);
+        }
+    }
+
+    /// Reads can also refer to a function declared later in a scope outside
+    /// the current function, which will have been declared by the time the
+    /// function is called. If it isn't, the read fails at runtime.
+    fn local(&mut self, name: &Token, slot: &Slot, read: bool) {
+        let mut in_function = false;
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            let declared = scope
+                .variables
+                .iter()
+                .rposition(|&symbol| symbol == name.symbol);
+            let later = || {
+                scope
+                    .functions
+                    .iter()
+                    .find(|(symbol, _)| *symbol == name.symbol)
+                    .map(|(_, index)| *index)
+            };
+            if let Some(index) = declared.or_else(|| (read && in_function).then(later).flatten()) {
+                slot.set(depth, index);
+                return;
+            }
+            in_function |= scope.call;
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! What a program may do, for running scripts that aren't trusted.
+
+use std::time::Duration;
+
+use crate::interpreter::MAX_FRAMES;
+
+/// Side effects a program may have besides printing. An `Interpreter` has
+/// none unless they are granted, so embedding one is safe by default; the
+/// command line grants them all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `import` and the `readFile` and `writeFile` built-ins.
+    pub filesystem: bool,
+    /// The `getEnv` built-in.
+    pub environment: bool,
+}
+
+impl Capabilities {
+    pub const ALL: Self = Self {
+        filesystem: true,
+        environment: true,
+    };
+}
+
+/// Bounds on what a run can use, counted from the start of each
+/// `Interpreter::interpret`. `None` is unlimited. Going over a limit ends
+/// the program with a runtime error that `catch` can't handle, except for
+/// `frames`, which fails the call like any other error.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Statements executed.
+    pub steps: Option<u64>,
+    /// Wall-clock time. It's only checked every few statements, so a run
+    /// may go slightly over.
+    pub time: Option<Duration>,
+    /// How deep calls can nest.
+    pub frames: usize,
+    /// Approximate bytes taken by the strings, lists and maps the program
+    /// creates. It only ever grows: memory the program lets go of isn't
+    /// given back.
+    pub allocation: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: None,
+            time: None,
+            frames: MAX_FRAMES,
+            allocation: None,
+        }
+    }
+}
@@ -1,59 +1,75 @@
 use std::collections::HashMap;
 
-use crate::Lox;
+use crate::{symbol::Symbol, Lox};
 
 use super::token::*;
 use LiteralVal::Nil;
 
 fn is_digit(c: char) -> bool {
-    let u = c as u8;
-    (u >= b'0') && (u <= b'9')
+    c.is_ascii_digit()
 }
 
 fn is_alpha(c: char) -> bool {
-    let u = c as u8;
-    (u >= b'a' && u <= b'z') || (u >= b'A' && u <= b'Z') || c == '_'
+    c.is_ascii_alphabetic() || c == '_'
 }
 
 fn is_alpha_numeric(c: char) -> bool {
     is_alpha(c) || is_digit(c)
 }
 
+/// Reserved words and the tokens they scan to.
+pub const KEYWORDS: [(&str, TokenType); 23] = [
+    ("and", TokenType::And),
+    ("break", TokenType::Break),
+    ("catch", TokenType::Catch),
+    ("class", TokenType::Class),
+    ("continue", TokenType::Continue),
+    ("else", TokenType::Else),
+    ("false", TokenType::False),
+    ("finally", TokenType::Finally),
+    ("for", TokenType::For),
+    ("fun", TokenType::Fun),
+    ("if", TokenType::If),
+    ("import", TokenType::Import),
+    ("nil", TokenType::Nil),
+    ("or", TokenType::Or),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("super", TokenType::Super),
+    ("this", TokenType::This),
+    ("throw", TokenType::Throw),
+    ("true", TokenType::True),
+    ("try", TokenType::Try),
+    ("var", TokenType::Var),
+    ("while", TokenType::While),
+];
+
+/// Positions (`start`, `current`) count `char`s, not bytes, so any UTF-8
+/// input can be scanned without slicing inside a character.
 pub struct Scanner {
-    source: String,
+    source: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: u32,
+    /// Position of the first character of the current line.
+    line_start: usize,
+    /// Column of the token being scanned, see `Token::column`.
+    column: u32,
     keywords: HashMap<&'static str, TokenType>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
         Self {
-            source,
+            source: source.chars().collect(),
             tokens: vec![],
             start: 0,
             current: 0,
             line: 1,
-            keywords: HashMap::from([
-                ("and", TokenType::And),
-                ("class", TokenType::Class),
-                ("else", TokenType::Else),
-                ("false", TokenType::False),
-                ("for", TokenType::For),
-                ("fun", TokenType::Fun),
-                ("if", TokenType::If),
-                ("nil", TokenType::Nil),
-                ("or", TokenType::Or),
-                ("print", TokenType::Print),
-                ("return", TokenType::Return),
-                ("super", TokenType::Super),
-                ("this", TokenType::This),
-                ("true", TokenType::True),
-                ("var", TokenType::Var),
-                ("while", TokenType::While),
-            ]),
+            line_start: 0,
+            column: 1,
+            keywords: HashMap::from(KEYWORDS),
         }
     }
 
@@ -69,12 +85,55 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen, Nil),
             '{' => self.add_token(TokenType::LeftBrace, Nil),
             '}' => self.add_token(TokenType::RightBrace, Nil),
+            '[' => self.add_token(TokenType::LeftBracket, Nil),
+            ']' => self.add_token(TokenType::RightBracket, Nil),
+            ':' => self.add_token(TokenType::Colon, Nil),
+            '?' => self.add_token(TokenType::Question, Nil),
             ',' => self.add_token(TokenType::Comma, Nil),
             '.' => self.add_token(TokenType::Dot, Nil),
-            '-' => self.add_token(TokenType::Minus, Nil),
-            '+' => self.add_token(TokenType::Plus, Nil),
+            '-' => {
+                let token = if self.match_char('=') {
+                    TokenType::MinusEqual
+                } else if self.match_char('-') {
+                    TokenType::MinusMinus
+                } else {
+                    TokenType::Minus
+                };
+                self.add_token(token, Nil)
+            }
+            '+' => {
+                let token = if self.match_char('=') {
+                    TokenType::PlusEqual
+                } else if self.match_char('+') {
+                    TokenType::PlusPlus
+                } else {
+                    TokenType::Plus
+                };
+                self.add_token(token, Nil)
+            }
             ';' => self.add_token(TokenType::Semicolon, Nil),
-            '*' => self.add_token(TokenType::Star, Nil),
+            '%' => self.add_token(TokenType::Percent, Nil),
+            '&' => self.add_token(TokenType::Ampersand, Nil),
+            '|' => self.add_token(TokenType::Pipe, Nil),
+            '^' => self.add_token(TokenType::Caret, Nil),
+            '~' => {
+                let token = if self.match_char('/') {
+                    TokenType::TildeSlash
+                } else {
+                    TokenType::Tilde
+                };
+                self.add_token(token, Nil)
+            }
+            '*' => {
+                let token = if self.match_char('*') {
+                    TokenType::StarStar
+                } else if self.match_char('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                };
+                self.add_token(token, Nil)
+            }
             '!' => {
                 let token = if self.match_char('=') {
                     TokenType::BangEqual
@@ -95,6 +154,8 @@ impl Scanner {
             '<' => {
                 let token = if self.match_char('=') {
                     TokenType::LessEqual
+                } else if self.match_char('<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -103,6 +164,8 @@ impl Scanner {
             '>' => {
                 let token = if self.match_char('=') {
                     TokenType::GreaterEqual
+                } else if self.match_char('>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -115,12 +178,14 @@ impl Scanner {
                     }
                 } else if self.match_char('*') {
                     self.block_comment(lox);
+                } else if self.match_char('=') {
+                    self.add_token(TokenType::SlashEqual, Nil)
                 } else {
                     self.add_token(TokenType::Slash, Nil)
                 }
             }
             ' ' | '\t' | '\r' => (),
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
             '"' => self.string(lox),
             _ => {
                 if is_digit(c) {
@@ -149,20 +214,28 @@ impl Scanner {
                 depth += 1;
                 self.advance();
             } else if self.peek() == '\n' {
-                self.line += 1;
+                self.advance();
+                self.new_line();
+                continue;
             }
 
             self.advance();
         }
     }
 
+    /// Called after consuming a `\n`.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn identifier(&mut self) {
         while is_alpha_numeric(self.peek()) {
             self.advance();
         }
 
-        let text = &self.source[self.start..self.current];
-        if let Some(keyword) = self.keywords.get(text) {
+        let text = self.text(self.start, self.current);
+        if let Some(keyword) = self.keywords.get(text.as_str()) {
             self.add_token(keyword.clone(), Nil)
         } else {
             self.add_token(TokenType::Identifier, Nil);
@@ -182,28 +255,30 @@ impl Scanner {
             }
         }
 
-        let substr = String::from(&self.source[self.start..self.current]);
-        self.add_token(
-            TokenType::Number,
-            LiteralVal::Number(substr.parse::<f32>().unwrap()),
-        )
+        // Digits with an optional fraction always parse; literals too large
+        // for an `f32` become infinity.
+        let value = self
+            .text(self.start, self.current)
+            .parse()
+            .unwrap_or(f32::INFINITY);
+        self.add_token(TokenType::Number, LiteralVal::Number(value))
     }
 
     fn string(&mut self, lox: &mut Lox) {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.advance() == '\n' {
+                self.new_line();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            lox.error(self.line, "Unterminated string.")
+            lox.error(self.line, "Unterminated string.");
+            return;
         }
 
         self.advance(); // The closing "
 
-        let value = String::from(&self.source[self.start + 1..self.current - 1]);
+        let value = self.text(self.start + 1, self.current - 1);
         self.add_token(TokenType::String, LiteralVal::Str(value));
     }
 
@@ -236,7 +311,11 @@ impl Scanner {
     }
 
     fn char_at(&self, i: usize) -> char {
-        self.source.chars().nth(i).unwrap()
+        self.source[i]
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.source[start..end].iter().collect()
     }
 
     fn advance(&mut self) -> char {
@@ -245,28 +324,37 @@ impl Scanner {
     }
 
     fn add_token(&mut self, token_type: TokenType, literal: LiteralVal) {
-        let text = self.source.get(self.start..self.current).unwrap();
+        let text = self.text(self.start, self.current);
+        let symbol = match token_type {
+            TokenType::Identifier => Symbol::intern(&text),
+            _ => Symbol::default(),
+        };
         self.tokens.push(Token {
             token_type,
-            lexeme: text.to_string(),
+            lexeme: text,
+            symbol,
             literal,
             line: self.line,
+            column: self.column,
         });
     }
 
     pub fn scan_tokens(&mut self, lox: &mut Lox) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.column = (self.start - self.line_start + 1) as u32;
             self.scan_token(lox)
         }
 
         self.tokens.push(Token {
             token_type: TokenType::EOF,
             lexeme: String::new(),
+            symbol: Symbol::default(),
             literal: Nil,
             line: self.line,
+            column: (self.current - self.line_start + 1) as u32,
         });
 
-        self.tokens.clone()
+        std::mem::take(&mut self.tokens)
     }
 }
@@ -0,0 +1,195 @@
+//! Saving the globals of a session as Lox source and running it again
+//! later, so a REPL session can be picked up where it was left or a
+//! program can start from prepared state.
+
+use std::{cell::RefCell, io, path::Path, rc::Rc};
+
+use crate::{
+    environment::Environment,
+    expr::{Expr, Slot},
+    interpreter::Interpreter,
+    lint::Lint,
+    printer::Printer,
+    stmt::{FunctionDecl, Stmt},
+    symbol::Symbol,
+    token::{LiteralVal, Token, TokenType},
+    Lox,
+};
+
+/// A program that defines the globals of an environment again: a `var`
+/// for each value, a `fun` for each function declared at the top level and
+/// an `import` for each module. Built-in functions are already there and
+/// aren't saved.
+///
+/// Values are saved as they are now, so lists and maps that were shared
+/// are restored as copies.
+pub struct Snapshot {
+    pub source: String,
+    /// Globals in `source`, by name.
+    pub saved: Vec<Symbol>,
+    /// Globals that can't be written as source, and why.
+    pub skipped: Vec<(Symbol, String)>,
+}
+
+impl Snapshot {
+    pub fn take(globals: &Rc<RefCell<Environment>>) -> Self {
+        let mut statements = vec![];
+        let mut saved = vec![];
+        let mut skipped = vec![];
+        let variables = globals.borrow().variables();
+        for (name, value) in variables {
+            match declaration(globals, name, &value) {
+                Ok(statement) => {
+                    statements.push(statement);
+                    saved.push(name);
+                }
+                Err(reason) => skipped.push((name, reason)),
+            }
+        }
+        Self {
+            source: Printer::new().print(&statements),
+            saved,
+            skipped,
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, &self.source)
+    }
+}
+
+/// Runs a saved snapshot in `environment`. Warnings were reported when the
+/// globals were first defined, so they aren't reported again.
+pub fn restore<P: AsRef<Path>>(
+    lox: &mut Lox,
+    interpreter: &mut Interpreter,
+    environment: &Rc<RefCell<Environment>>,
+    path: P,
+) -> io::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let allowed = std::mem::replace(&mut lox.allowed, Lint::ALL.iter().copied().collect());
+    lox.run(source, interpreter, environment, false);
+    lox.allowed = allowed;
+    Ok(())
+}
+
+fn declaration(
+    globals: &Rc<RefCell<Environment>>,
+    name: Symbol,
+    value: &LiteralVal,
+) -> Result<Stmt, String> {
+    match value {
+        LiteralVal::UnInit => Ok(Stmt::Var(identifier(name), None, None)),
+        LiteralVal::Module(module) => {
+            let path = module
+                .path
+                .to_str()
+                .filter(|path| !path.contains('"'))
+                .ok_or("its path can't be written as a string")?;
+            let path = Token {
+                literal: LiteralVal::Str(path.to_string()),
+                ..token(TokenType::String, format!("\"{path}\""))
+            };
+            Ok(Stmt::Import(path, identifier(name)))
+        }
+        LiteralVal::Function(function)
+            if Rc::ptr_eq(&function.closure, globals)
+                && function.declaration.name.as_ref().map(|name| name.symbol) == Some(name) =>
+        {
+            Ok(Stmt::Function(Rc::clone(&function.declaration)))
+        }
+        _ => {
+            let value = expression(globals, value, &mut vec![])?;
+            Ok(Stmt::Var(identifier(name), None, Some(value)))
+        }
+    }
+}
+
+/// `value` as an expression that evaluates to it. `containing` has the
+/// lists and maps `value` is inside of, to catch ones that contain
+/// themselves.
+fn expression(
+    globals: &Rc<RefCell<Environment>>,
+    value: &LiteralVal,
+    containing: &mut Vec<*const ()>,
+) -> Result<Expr, String> {
+    match value {
+        LiteralVal::Str(s) if s.contains('"') => {
+            Err("it has a string with a '\"', which literals can't hold".to_string())
+        }
+        LiteralVal::Number(_)
+        | LiteralVal::NaN
+        | LiteralVal::Str(_)
+        | LiteralVal::Boolean(_)
+        | LiteralVal::Nil => Ok(Expr::Literal(value.clone())),
+        LiteralVal::List(elements) => {
+            enter(containing, Rc::as_ptr(elements).cast())?;
+            let elements = elements
+                .borrow()
+                .iter()
+                .map(|element| expression(globals, element, containing))
+                .collect::<Result<_, _>>()?;
+            containing.pop();
+            Ok(Expr::List(elements))
+        }
+        LiteralVal::Map(map) => {
+            enter(containing, Rc::as_ptr(map).cast())?;
+            let entries = map
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    let key = Expr::Literal(key.to_value());
+                    Ok((key, expression(globals, value, containing)?))
+                })
+                .collect::<Result<_, String>>()?;
+            containing.pop();
+            Ok(Expr::Map(
+                token(TokenType::LeftBrace, "{".to_string()),
+                entries,
+            ))
+        }
+        LiteralVal::Native(native) => Ok(Expr::Variable(
+            identifier(Symbol::intern(native.name)),
+            Slot::default(),
+        )),
+        LiteralVal::Function(function) if Rc::ptr_eq(&function.closure, globals) => {
+            let declaration = &function.declaration;
+            Ok(Expr::Lambda(Rc::new(FunctionDecl {
+                name: None,
+                params: declaration.params.clone(),
+                param_types: declaration.param_types.clone(),
+                return_type: declaration.return_type.clone(),
+                body: declaration.body.clone(),
+            })))
+        }
+        LiteralVal::Function(_) => Err("it closes over local variables".to_string()),
+        LiteralVal::Module(_) => Err("it has a module inside a collection".to_string()),
+        LiteralVal::UnInit => unreachable!("collections only hold initialized values"),
+    }
+}
+
+fn enter(containing: &mut Vec<*const ()>, collection: *const ()) -> Result<(), String> {
+    if containing.contains(&collection) {
+        return Err("it contains itself".to_string());
+    }
+    containing.push(collection);
+    Ok(())
+}
+
+fn identifier(name: Symbol) -> Token {
+    Token {
+        symbol: name,
+        ..token(TokenType::Identifier, name.name().to_string())
+    }
+}
+
+fn token(token_type: TokenType, lexeme: String) -> Token {
+    Token {
+        token_type,
+        lexeme,
+        symbol: Symbol::default(),
+        literal: LiteralVal::Nil,
+        line: 0,
+        column: 0,
+    }
+}
@@ -1,21 +1,82 @@
+use std::rc::Rc;
+
 use crate::{expr::Expr, token::Token};
 
-#[derive(Clone)]
+/// Parameters and body of a `fun` declaration or a lambda expression.
+/// Lambdas have no name.
+#[derive(Debug, PartialEq)]
+pub struct FunctionDecl {
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    /// Type annotation of each parameter, in the same order as `params`.
+    pub param_types: Vec<Option<Token>>,
+    pub return_type: Option<Token>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Stmt {
     Block(Vec<Stmt>),
+    Break(Token),
+    Continue(Token),
     Expression(Expr),
-    // Function {
-    //     name: Token,
-    //     params: Vec<Token>,
-    //     body: Vec<Stmt>,
-    // },
-    // If {
-    //     condition: Expr,
-    //     then_branch: Box<Stmt>,
-    //     else_branch: Box<Stmt>,
-    // },
-    Print(Expr),
-    // Return(Token, Expr),
-    Var(Token, Option<Expr>),
-    // While(Expr, Box<Stmt>),
+    Function(Rc<FunctionDecl>),
+    If {
+        keyword: Token,
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    /// Path string token and the name the module is bound to.
+    Import(Token, Token),
+    /// `print` keyword and the value.
+    Print(Token, Expr),
+    Return(Token, Option<Expr>),
+    /// `throw` keyword and the value.
+    Throw(Token, Expr),
+    /// A `try` block with a handler, a `finally` block or both. The handler
+    /// is the name the caught value is bound to and its body.
+    Try {
+        keyword: Token,
+        body: Vec<Stmt>,
+        catch: Option<(Token, Vec<Stmt>)>,
+        finally: Option<Vec<Stmt>>,
+    },
+    /// Name, type annotation and initializer.
+    Var(Token, Option<Token>, Option<Expr>),
+    /// `keyword` is `for` for desugared `for` loops, whose increment runs
+    /// after every iteration, including ones cut short by `continue`.
+    While {
+        keyword: Token,
+        condition: Expr,
+        body: Box<Stmt>,
+        increment: Option<Expr>,
+    },
+}
+
+impl Stmt {
+    /// Line the statement starts on, if it has a token to tell. Only
+    /// expression statements made of literals don't.
+    pub fn line(&self) -> Option<u32> {
+        self.token().map(|token| token.line)
+    }
+
+    /// The statement's first token, as far as the tree still has it.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Stmt::Block(statements) => statements.first()?.token(),
+            Stmt::Break(keyword)
+            | Stmt::Continue(keyword)
+            | Stmt::Print(keyword, _)
+            | Stmt::Return(keyword, _)
+            | Stmt::Throw(keyword, _)
+            | Stmt::Try { keyword, .. }
+            | Stmt::If { keyword, .. }
+            | Stmt::While { keyword, .. } => Some(keyword),
+            Stmt::Expression(expr) => expr.token(),
+            Stmt::Function(declaration) => declaration.name.as_ref(),
+            Stmt::Import(path, _) => Some(path),
+            Stmt::Var(name, ..) => Some(name),
+        }
+    }
 }
@@ -0,0 +1,54 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+/// An interned identifier. Comparing and hashing a symbol is as cheap as
+/// for a `u32`, which is what global lookups key on. The default symbol is
+/// the empty string, used by tokens that aren't identifiers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+impl Interner {
+    fn new() -> Self {
+        let mut interner = Self {
+            symbols: HashMap::new(),
+            names: vec![],
+        };
+        interner.intern("");
+        interner
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = name.into();
+        self.names.push(Rc::clone(&name));
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    pub fn name(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::clone(&interner.borrow().names[self.0 as usize]))
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// Measures how long each phase of `Lox::run` takes. `Lox` reports the
+/// durations once it is done, so phases that were reached before a compile
+/// error are still reported.
+pub(crate) struct PhaseTimer {
+    enabled: bool,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl PhaseTimer {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last: Instant::now(),
+            phases: vec![],
+        }
+    }
+
+    /// Ends the phase called `name`; the next one starts now.
+    pub fn lap(&mut self, name: &'static str) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        self.phases.push((name, now - self.last));
+        self.last = now;
+    }
+
+    /// Writes each phase and their total to `out`, one per line.
+    pub fn report(&self, out: &mut dyn Write) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let total: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        for (name, duration) in self.phases.iter().chain([&("total", total)]) {
+            writeln!(out, "{name:>8}: {:10.3} ms", duration.as_secs_f64() * 1000.)?;
+        }
+        Ok(())
+    }
+}
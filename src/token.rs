@@ -1,6 +1,14 @@
-use crate::interpreter::RuntimeError;
+use std::{cell::RefCell, fmt, rc::Rc};
 
-#[derive(Clone, Debug, PartialEq)]
+use crate::{
+    callable::{LoxFunction, NativeFunction},
+    interpreter::RuntimeError,
+    map::LoxMap,
+    module::Module,
+    symbol::Symbol,
+};
+
+#[derive(Clone, Debug)]
 pub enum LiteralVal {
     Number(f32),
     Str(String),
@@ -8,20 +16,79 @@ pub enum LiteralVal {
     Nil,
     NaN,
     UnInit,
+    Module(Module),
+    List(Rc<RefCell<Vec<LiteralVal>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Native(NativeFunction),
+    Function(LoxFunction),
 }
 
 impl LiteralVal {
-    pub fn number_operand(&self, operator: Token) -> Result<f32, RuntimeError> {
+    pub fn number_operand(&self, operator: &Token) -> Result<f32, RuntimeError> {
         match self {
             Self::Number(n) => Ok(*n),
             Self::NaN => Ok(f32::NAN),
-            _ => Err(RuntimeError(
-                Some(operator),
+            _ => Err(RuntimeError::new(
+                Some(operator.clone()),
                 "Operand must be a number.".to_string(),
             )),
         }
     }
+    /// Bitwise operators work on numbers that are exact 32-bit integers.
+    pub fn integer_operand(&self, operator: &Token) -> Result<i32, RuntimeError> {
+        match self {
+            Self::Number(n) if n.fract() == 0. && *n >= i32::MIN as f32 && *n < i32::MAX as f32 => {
+                Ok(*n as i32)
+            }
+            _ => Err(RuntimeError::new(
+                Some(operator.clone()),
+                "Operands must be 32-bit integers.".to_string(),
+            )),
+        }
+    }
+
+    /// Checks that `self` is a whole number that can index a sequence of
+    /// `len` elements.
+    pub fn index_operand(&self, token: &Token, len: usize) -> Result<usize, RuntimeError> {
+        let n = match self {
+            Self::Number(n) if n.fract() == 0. => *n,
+            _ => {
+                return Err(RuntimeError::new(
+                    Some(token.clone()),
+                    "Index must be an integer.".to_string(),
+                ))
+            }
+        };
+        if n < 0. || n >= len as f32 {
+            return Err(RuntimeError::new(
+                Some(token.clone()),
+                format!("Index {n} is out of bounds for length {len}."),
+            ));
+        }
+        Ok(n as usize)
+    }
+
+    /// Like `print`, but strings are quoted. Used for values nested inside
+    /// collections.
+    pub fn repr(&self) -> Result<String, RuntimeError> {
+        self.repr_inside(&mut vec![])
+    }
+
     pub fn print(&self) -> Result<String, RuntimeError> {
+        self.print_inside(&mut vec![])
+    }
+
+    fn repr_inside(&self, containing: &mut Vec<*const ()>) -> Result<String, RuntimeError> {
+        match self {
+            Self::Str(s) => Ok(format!("{s:?}")),
+            _ => self.print_inside(containing),
+        }
+    }
+
+    /// `containing` has the lists and maps being printed that `self` is
+    /// inside of. A collection that contains itself is printed as `[...]`
+    /// or `{...}` the second time around.
+    fn print_inside(&self, containing: &mut Vec<*const ()>) -> Result<String, RuntimeError> {
         match self {
             Self::Number(n) => {
                 let mut res = n.to_string();
@@ -35,7 +102,38 @@ impl LiteralVal {
             Self::Boolean(b) => Ok(b.to_string()),
             Self::Nil => Ok("nil".to_string()),
             Self::NaN => Ok("Nan".to_string()),
-            Self::UnInit => Err(RuntimeError(
+            Self::Module(module) => Ok(format!("<module {}>", module.name)),
+            Self::List(list) => {
+                let address = Rc::as_ptr(list).cast();
+                if containing.contains(&address) {
+                    return Ok("[...]".to_string());
+                }
+                containing.push(address);
+                let elements = list
+                    .borrow()
+                    .iter()
+                    .map(|element| element.repr_inside(containing))
+                    .collect::<Result<Vec<_>, _>>();
+                containing.pop();
+                Ok(format!("[{}]", elements?.join(", ")))
+            }
+            Self::Map(map) => {
+                let address = Rc::as_ptr(map).cast();
+                if containing.contains(&address) {
+                    return Ok("{...}".to_string());
+                }
+                containing.push(address);
+                let entries = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| Ok(format!("{key}: {}", value.repr_inside(containing)?)))
+                    .collect::<Result<Vec<_>, RuntimeError>>();
+                containing.pop();
+                Ok(format!("{{{}}}", entries?.join(", ")))
+            }
+            Self::Native(native) => Ok(format!("{native:?}")),
+            Self::Function(function) => Ok(format!("{function:?}")),
+            Self::UnInit => Err(RuntimeError::new(
                 None,
                 "Can't print unitiliazed variable.".to_string(),
             )),
@@ -43,9 +141,28 @@ impl LiteralVal {
     }
 }
 
-impl Into<Result<LiteralVal, RuntimeError>> for LiteralVal {
-    fn into(self) -> Result<LiteralVal, RuntimeError> {
-        Ok(self)
+/// Lists and maps are references, like functions and modules: each one is
+/// only equal to itself, however alike their contents are.
+impl PartialEq for LiteralVal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::Str(a), Self::Str(b)) => a == b,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Nil, Self::Nil) | (Self::NaN, Self::NaN) | (Self::UnInit, Self::UnInit) => true,
+            (Self::Module(a), Self::Module(b)) => a == b,
+            (Self::List(a), Self::List(b)) => Rc::ptr_eq(a, b),
+            (Self::Map(a), Self::Map(b)) => Rc::ptr_eq(a, b),
+            (Self::Native(a), Self::Native(b)) => a == b,
+            (Self::Function(a), Self::Function(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl From<LiteralVal> for Result<LiteralVal, RuntimeError> {
+    fn from(val: LiteralVal) -> Self {
+        Ok(val)
     }
 }
 
@@ -53,13 +170,33 @@ impl Into<Result<LiteralVal, RuntimeError>> for LiteralVal {
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
+    /// Interned `lexeme` of identifiers, the empty symbol otherwise.
+    pub symbol: Symbol,
     pub literal: LiteralVal,
     pub line: u32,
+    /// Column of the first character, counting `char`s from 1. For strings
+    /// that span lines, `line` is the last line but `column` is on the
+    /// first.
+    pub column: u32,
+}
+
+/// Tokens are equal if they were written the same, wherever they are.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_type == other.token_type
+            && self.lexeme == other.lexeme
+            && self.symbol == other.symbol
+            && self.literal == other.literal
+    }
 }
 
-impl ToString for Token {
-    fn to_string(&self) -> String {
-        format!("{:?} {} {:?}", self.token_type, self.lexeme, self.literal)
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} {:?}",
+            self.token_type, self.lexeme, self.literal
+        )
     }
 }
 
@@ -70,13 +207,22 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
+    Question,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     /// One or two character tokens
     Bang,
@@ -87,6 +233,16 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    StarStar,
+    TildeSlash,
+    MinusEqual,
+    MinusMinus,
+    PlusEqual,
+    PlusPlus,
+    SlashEqual,
+    StarEqual,
 
     /// Literals
     Identifier,
@@ -95,19 +251,26 @@ pub enum TokenType {
 
     /// Keywords
     And,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
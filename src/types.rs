@@ -0,0 +1,483 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    expr::Expr,
+    stmt::{FunctionDecl, Stmt},
+    token::{LiteralVal, Token, TokenType},
+    Lox,
+};
+
+/// Static type of an expression. `Any` is what unannotated code gets, and it
+/// is compatible with every other type, so untyped code is never rejected.
+#[derive(Clone, Debug)]
+pub enum Type {
+    Any,
+    Number,
+    String,
+    Bool,
+    Nil,
+    List,
+    Map,
+    Module,
+    /// A function, with its signature when it is known statically.
+    Function(Option<Rc<Signature>>),
+}
+
+#[derive(Debug)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret: Type,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "any" => Some(Self::Any),
+            "number" => Some(Self::Number),
+            "string" => Some(Self::String),
+            "bool" => Some(Self::Bool),
+            "nil" => Some(Self::Nil),
+            "list" => Some(Self::List),
+            "map" => Some(Self::Map),
+            "module" => Some(Self::Module),
+            "fun" => Some(Self::Function(None)),
+            _ => None,
+        }
+    }
+
+    /// Whether a value of type `self` may be used where `other` is expected.
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Any, _) | (_, Self::Any) => true,
+            (Self::Function(_), Self::Function(_)) => true,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        !matches!(self, Self::Any)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Any => "any",
+            Self::Number => "number",
+            Self::String => "string",
+            Self::Bool => "bool",
+            Self::Nil => "nil",
+            Self::List => "list",
+            Self::Map => "map",
+            Self::Module => "module",
+            Self::Function(_) => "fun",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Gradual type checker. Errors are only reported where an annotation is
+/// involved: initializing or assigning an annotated variable, returning from
+/// a function with a return type and calling a function with typed
+/// parameters. Everything else is left to the interpreter.
+pub struct TypeChecker {
+    /// Declared type of every variable in scope, innermost scope last.
+    scopes: Vec<HashMap<String, Type>>,
+    /// Declared return type of each enclosing function.
+    returns: Vec<Type>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            returns: vec![],
+        }
+    }
+
+    pub fn check(&mut self, lox: &mut Lox, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(lox, statement);
+        }
+    }
+
+    /// Reports functions that can finish without a `return` although their
+    /// return type doesn't allow `nil`. `DefiniteAssignment` finds them, as
+    /// it already follows every path through a function.
+    pub fn missing_returns(lox: &mut Lox, return_types: &[Token]) {
+        for token in return_types {
+            match Type::from_name(&token.lexeme) {
+                Some(Type::Any | Type::Nil) | None => (),
+                Some(declared) => lox.error_token(
+                    token,
+                    &format!("Missing return in function declared to return {declared}."),
+                ),
+            }
+        }
+    }
+
+    fn statement(&mut self, lox: &mut Lox, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(statements) => self.block(lox, statements),
+            Stmt::Break(_) | Stmt::Continue(_) => (),
+            Stmt::Expression(expr) | Stmt::Print(_, expr) | Stmt::Throw(_, expr) => {
+                self.expression(lox, expr);
+            }
+            Stmt::Function(declaration) => {
+                let signature = self.signature(lox, declaration);
+                if let Some(name) = &declaration.name {
+                    self.declare(name, function_type(declaration, &signature));
+                }
+                self.function(lox, declaration, &signature);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(lox, condition);
+                self.statement(lox, then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(lox, else_branch);
+                }
+            }
+            Stmt::Import(_, name) => self.declare(name, Type::Module),
+            Stmt::Return(keyword, value) => {
+                let found = match value {
+                    Some(value) => self.expression(lox, value),
+                    None => Type::Nil,
+                };
+                if let Some(expected) = self.returns.last() {
+                    if !found.is_compatible(expected) {
+                        lox.error_token(
+                            keyword,
+                            &format!("Function returns {expected} but this returns {found}."),
+                        );
+                    }
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.block(lox, body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(HashMap::new());
+                    self.declare(name, Type::Any);
+                    self.check(lox, handler);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(lox, finally);
+                }
+            }
+            Stmt::Var(name, annotation, initializer) => {
+                let declared = self.annotation(lox, annotation.as_ref());
+                let found = initializer
+                    .as_ref()
+                    .map(|initializer| self.expression(lox, initializer));
+                if let Some(found) = &found {
+                    if !found.is_compatible(&declared) {
+                        lox.error_token(
+                            name,
+                            &format!(
+                                "Variable '{}' is declared as {declared} but initialized with {found}.",
+                                name.lexeme
+                            ),
+                        );
+                    }
+                }
+                // Annotated lambdas keep their signature, so calls through
+                // the variable can still be checked, until another function
+                // is assigned to it.
+                let declared = match (declared, found) {
+                    (Type::Any, Some(found @ Type::Function(Some(_)))) => found,
+                    (declared, _) => declared,
+                };
+                self.declare(name, declared);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.expression(lox, condition);
+                self.statement(lox, body);
+                if let Some(increment) = increment {
+                    self.expression(lox, increment);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, lox: &mut Lox, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.check(lox, statements);
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, lox: &mut Lox, expr: &Expr) -> Type {
+        match expr {
+            Expr::Assign(name, value, _) => {
+                let found = self.expression(lox, value);
+                self.check_assignment(lox, name, &found);
+                found
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.expression(lox, left);
+                let right = self.expression(lox, right);
+                binary(operator, &left, &right)
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.expression(lox, callee);
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|argument| self.expression(lox, argument))
+                    .collect();
+                self.call(lox, &callee, paren, &arguments)
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(lox, condition);
+                let then_type = self.expression(lox, then_branch);
+                let else_type = self.expression(lox, else_branch);
+                if then_type.is_known()
+                    && std::mem::discriminant(&then_type) == std::mem::discriminant(&else_type)
+                {
+                    then_type
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Compound {
+                target,
+                operator,
+                value,
+                ..
+            } => {
+                let current = self.expression(lox, target);
+                let value = self.expression(lox, value);
+                if let Expr::Variable(name, _) = target.as_ref() {
+                    if !value.is_compatible(&current) {
+                        lox.error_token(
+                            operator,
+                            &format!(
+                                "Can't use '{}' with {value} on '{}', which is declared as {current}.",
+                                operator.lexeme, name.lexeme
+                            ),
+                        );
+                    }
+                }
+                binary(operator, &current, &value)
+            }
+            Expr::Get(object, _) => {
+                self.expression(lox, object);
+                Type::Any
+            }
+            Expr::Grouping(expr) => self.expression(lox, expr),
+            Expr::Index { object, index, .. } => {
+                self.expression(lox, object);
+                self.expression(lox, index);
+                Type::Any
+            }
+            Expr::IndexSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(lox, object);
+                self.expression(lox, index);
+                self.expression(lox, value)
+            }
+            Expr::Lambda(declaration) => {
+                let signature = self.signature(lox, declaration);
+                self.function(lox, declaration, &signature);
+                function_type(declaration, &signature)
+            }
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(lox, element);
+                }
+                Type::List
+            }
+            Expr::Literal(value) => match value {
+                LiteralVal::Number(_) | LiteralVal::NaN => Type::Number,
+                LiteralVal::Str(_) => Type::String,
+                LiteralVal::Boolean(_) => Type::Bool,
+                LiteralVal::Nil => Type::Nil,
+                _ => Type::Any,
+            },
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.expression(lox, key);
+                    self.expression(lox, value);
+                }
+                Type::Map
+            }
+            Expr::Set(object, _, value) => {
+                self.expression(lox, object);
+                self.expression(lox, value)
+            }
+            Expr::Unary { operator, right } => {
+                self.expression(lox, right);
+                match operator.token_type {
+                    TokenType::Bang => Type::Bool,
+                    _ => Type::Number,
+                }
+            }
+            Expr::Variable(name, _) => self.lookup(name),
+        }
+    }
+
+    fn call(&mut self, lox: &mut Lox, callee: &Type, paren: &Token, arguments: &[Type]) -> Type {
+        let signature = match callee {
+            Type::Function(Some(signature)) => signature,
+            _ => return Type::Any,
+        };
+
+        if signature.params.len() != arguments.len() {
+            lox.error_token(
+                paren,
+                &format!(
+                    "Expected {} arguments but got {}.",
+                    signature.params.len(),
+                    arguments.len()
+                ),
+            );
+        } else {
+            for (i, (param, argument)) in signature.params.iter().zip(arguments).enumerate() {
+                if !argument.is_compatible(param) {
+                    lox.error_token(
+                        paren,
+                        &format!("Argument {} should be {param} but is {argument}.", i + 1),
+                    );
+                }
+            }
+        }
+        signature.ret.clone()
+    }
+
+    fn signature(&mut self, lox: &mut Lox, declaration: &FunctionDecl) -> Rc<Signature> {
+        let params = declaration
+            .param_types
+            .iter()
+            .map(|annotation| self.annotation(lox, annotation.as_ref()))
+            .collect();
+        let ret = self.annotation(lox, declaration.return_type.as_ref());
+        Rc::new(Signature { params, ret })
+    }
+
+    fn function(&mut self, lox: &mut Lox, declaration: &FunctionDecl, signature: &Signature) {
+        self.scopes.push(HashMap::new());
+        self.returns.push(signature.ret.clone());
+        for (param, param_type) in declaration.params.iter().zip(&signature.params) {
+            self.declare(param, param_type.clone());
+        }
+        self.check(lox, &declaration.body);
+        self.returns.pop();
+        self.scopes.pop();
+    }
+
+    fn annotation(&mut self, lox: &mut Lox, annotation: Option<&Token>) -> Type {
+        let Some(token) = annotation else {
+            return Type::Any;
+        };
+        Type::from_name(&token.lexeme).unwrap_or_else(|| {
+            lox.error_token(token, &format!("Unknown type '{}'.", token.lexeme));
+            Type::Any
+        })
+    }
+
+    fn check_assignment(&mut self, lox: &mut Lox, name: &Token, found: &Type) {
+        let declared = self.lookup(name);
+        if !found.is_compatible(&declared) {
+            lox.error_token(
+                name,
+                &format!(
+                    "Can't assign {found} to '{}', which is declared as {declared}.",
+                    name.lexeme
+                ),
+            );
+        }
+        // The signature came from the function the variable was declared
+        // with, so it no longer says how calls through it are checked.
+        if let Type::Function(Some(_)) = declared {
+            if let Some(declared) = self
+                .scopes
+                .iter_mut()
+                .rev()
+                .find_map(|scope| scope.get_mut(&name.lexeme))
+            {
+                *declared = Type::Function(None);
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Token, declared: Type) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), declared);
+    }
+
+    /// Declared type of a variable. Variables the checker hasn't seen, like
+    /// built-ins or globals from earlier REPL lines, are `Any`.
+    fn lookup(&self, name: &Token) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .cloned()
+            .unwrap_or(Type::Any)
+    }
+}
+
+/// Only functions with at least one annotation get a checked signature, so
+/// calls to untyped functions behave exactly as they did before.
+fn function_type(declaration: &FunctionDecl, signature: &Rc<Signature>) -> Type {
+    let annotated =
+        declaration.return_type.is_some() || declaration.param_types.iter().any(Option::is_some);
+    Type::Function(annotated.then(|| Rc::clone(signature)))
+}
+
+/// Result type of a binary operator. Operands that don't fit are not an
+/// error here, the interpreter reports them if the code ever runs.
+fn binary(operator: &Token, left: &Type, right: &Type) -> Type {
+    match operator.token_type {
+        TokenType::Comma => right.clone(),
+        TokenType::EqualEqual
+        | TokenType::BangEqual
+        | TokenType::Greater
+        | TokenType::GreaterEqual
+        | TokenType::Less
+        | TokenType::LessEqual => Type::Bool,
+        TokenType::Plus => match (left, right) {
+            (Type::Number, Type::Number) => Type::Number,
+            (Type::String, Type::String) => Type::String,
+            _ => Type::Any,
+        },
+        _ => Type::Number,
+    }
+}
@@ -0,0 +1,107 @@
+//! Helpers shared by the integration tests. Each test binary only uses some
+//! of them.
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crafting_interpreters::{environment::Environment, interpreter::Interpreter, Lox};
+
+/// A `Write` whose contents can still be read once `Lox` owns it.
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.borrow())
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A `Lox` with its output and diagnostics captured, and an interpreter and
+/// globals to run programs in. The interpreter has no capabilities, as
+/// `Interpreter::new` makes it; tests that need them grant them.
+pub struct Fixture {
+    pub lox: Lox,
+    pub interpreter: Interpreter,
+    pub environment: Rc<RefCell<Environment>>,
+    pub output: Capture,
+    pub diagnostics: Capture,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let output = Capture::default();
+        let diagnostics = Capture::default();
+        let mut lox = Lox::default();
+        lox.set_output(output.clone());
+        lox.set_diagnostics(diagnostics.clone());
+        Self {
+            lox,
+            interpreter: Interpreter::new(),
+            environment: Rc::new(RefCell::new(Environment::global())),
+            output,
+            diagnostics,
+        }
+    }
+
+    pub fn run(&mut self, source: &str) {
+        self.lox.run(
+            source.to_string(),
+            &mut self.interpreter,
+            &self.environment,
+            false,
+        );
+    }
+
+    pub fn run_file(&mut self, path: &Path) {
+        self.lox
+            .run_file(path, &mut self.interpreter, &self.environment);
+    }
+}
+
+/// Every `.lox` file under `dir`, sorted.
+pub fn lox_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "lox") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
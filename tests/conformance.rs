@@ -0,0 +1,230 @@
+//! Runs every `.lox` file under `tests/` and checks it against the
+//! annotations in its comments, in the format of the official Crafting
+//! Interpreters test suite:
+//!
+//! - `// expect: value` is a line the program prints.
+//! - `// expect runtime error: message` is the runtime error the program
+//!   stops with, raised on that line.
+//! - `// expect trace: in f, called on line N` is the next line of the
+//!   runtime error's call trace.
+//! - `// Error at 'x': message` and `// Warning at 'x': message [code]` are
+//!   diagnostics reported for that line. `// [line N] Error...` reports
+//!   them for line N instead, for errors that can't share a line with a
+//!   comment.
+//!
+//! Every file is run twice, with and without the optimizer. Set `LOX_TEST`
+//! to only run files whose path contains its value. Files under
+//! `tests/fixtures` are loaded by other tests and aren't run on their own.
+
+mod common;
+
+use std::{env, fs, panic, path::Path};
+
+use common::{lox_files, Fixture};
+use crafting_interpreters::sandbox::Capabilities;
+
+#[derive(Default)]
+struct Expectations {
+    output: Vec<String>,
+    diagnostics: Vec<String>,
+    /// Message and line.
+    runtime_error: Option<(String, usize)>,
+    trace: Vec<String>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        let mut in_string = false;
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let Some(comment) = comment(line, &mut in_string) else {
+                continue;
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some((message.to_string(), line_number));
+            } else if let Some(call) = comment.strip_prefix("expect trace: ") {
+                expectations.trace.push(call.to_string());
+            } else if comment.starts_with("Error") || comment.starts_with("Warning") {
+                expectations
+                    .diagnostics
+                    .push(format!("[line {line_number}] {comment}"));
+            } else if comment.starts_with("[line ")
+                && (comment.contains("] Error") || comment.contains("] Warning"))
+            {
+                expectations.diagnostics.push(comment.to_string());
+            }
+        }
+        expectations
+    }
+}
+
+/// The text after the `// ` that starts a comment on `line`, if any.
+/// `in_string` says whether `line` starts inside a string literal, which
+/// can span lines, and is updated for the next one. Lox strings have no
+/// escapes, so every `"` starts or ends one.
+fn comment<'a>(line: &'a str, in_string: &mut bool) -> Option<&'a str> {
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => *in_string = !*in_string,
+            '/' if !*in_string && chars.next_if(|(_, next)| *next == '/').is_some() => {
+                return line[i + 2..].strip_prefix(' ');
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// What a run of a file printed, split up like `Expectations`.
+struct Outcome {
+    output: Vec<String>,
+    diagnostics: Vec<String>,
+    runtime_error: Option<(String, usize)>,
+    trace: Vec<String>,
+}
+
+fn run(path: &Path, optimize: bool) -> Outcome {
+    let mut fixture = Fixture::new();
+    fixture.lox.set_optimize(optimize);
+    fixture.interpreter.capabilities = Capabilities::ALL;
+    fixture.run_file(path);
+
+    // Runtime errors are a message followed by `[line N;token x]` and the
+    // indented trace.
+    let mut reported = vec![];
+    let mut runtime_error = None;
+    let mut trace = vec![];
+    let lines = fixture.diagnostics.lines();
+    let mut lines = lines.iter().peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with("[line ") {
+            reported.push(line.clone());
+            continue;
+        }
+        if let Some(call) = line.strip_prefix("  ") {
+            trace.push(call.to_string());
+            continue;
+        }
+        let error_line = lines
+            .next_if(|next| next.starts_with("[line ") && next.contains(';'))
+            .and_then(|next| next["[line ".len()..].split(';').next()?.parse().ok())
+            .unwrap_or(0);
+        runtime_error = Some((line.clone(), error_line));
+    }
+
+    Outcome {
+        output: fixture.output.lines(),
+        diagnostics: reported,
+        runtime_error,
+        trace,
+    }
+}
+
+/// Differences between what `path` should and did do, one per line.
+fn check(path: &Path, optimize: bool) -> Vec<String> {
+    let source = fs::read_to_string(path).unwrap();
+    let mut expected = Expectations::parse(&source);
+    let mut outcome = run(path, optimize);
+    let mut problems = vec![];
+
+    if expected.output != outcome.output {
+        problems.push("output differs:".to_string());
+        problems.extend(diff(&expected.output, &outcome.output));
+    }
+
+    expected.diagnostics.sort();
+    outcome.diagnostics.sort();
+    if expected.diagnostics != outcome.diagnostics {
+        problems.push("diagnostics differ:".to_string());
+        problems.extend(diff(&expected.diagnostics, &outcome.diagnostics));
+    }
+
+    if expected.runtime_error != outcome.runtime_error {
+        let describe = |error: &Option<(String, usize)>| match error {
+            Some((message, line)) => format!("'{message}' on line {line}"),
+            None => "none".to_string(),
+        };
+        problems.push(format!(
+            "runtime error: expected {}, got {}",
+            describe(&expected.runtime_error),
+            describe(&outcome.runtime_error)
+        ));
+    }
+
+    if expected.trace != outcome.trace {
+        problems.push("trace differs:".to_string());
+        problems.extend(diff(&expected.trace, &outcome.trace));
+    }
+    problems
+}
+
+/// Lines only in `expected` are marked `-`, lines only in `actual` `+`.
+fn diff(expected: &[String], actual: &[String]) -> Vec<String> {
+    let prefix = expected
+        .iter()
+        .zip(actual)
+        .take_while(|(e, a)| e == a)
+        .count();
+    let expected = &expected[prefix..];
+    let actual = &actual[prefix..];
+    let suffix = expected
+        .iter()
+        .rev()
+        .zip(actual.iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+
+    let mut lines = vec![];
+    lines.extend(
+        expected[..expected.len() - suffix]
+            .iter()
+            .map(|line| format!("  - {line}")),
+    );
+    lines.extend(
+        actual[..actual.len() - suffix]
+            .iter()
+            .map(|line| format!("  + {line}")),
+    );
+    lines
+}
+
+#[test]
+fn conformance() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let fixtures = root.join("fixtures");
+    let files: Vec<_> = lox_files(&root)
+        .into_iter()
+        .filter(|path| !path.starts_with(&fixtures))
+        .collect();
+    let filter = env::var("LOX_TEST").ok();
+
+    let mut failures = vec![];
+    let mut count = 0;
+    for path in &files {
+        let name = path.strip_prefix(&root).unwrap().display().to_string();
+        if filter.as_ref().is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+        count += 1;
+        for optimize in [false, true] {
+            let problems = panic::catch_unwind(|| check(path, optimize))
+                .unwrap_or_else(|_| vec!["the interpreter panicked".to_string()]);
+            if !problems.is_empty() {
+                let mode = if optimize { " (optimized)" } else { "" };
+                failures.push(format!("{name}{mode}\n{}", problems.join("\n")));
+            }
+        }
+    }
+
+    assert!(count > 0, "no tests matched");
+    assert!(
+        failures.is_empty(),
+        "{} of {count} files failed:\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
@@ -0,0 +1,250 @@
+//! Steps through `tests/fixtures/debugger/steps.lox` with the console
+//! frontend and over the Debug Adapter Protocol.
+
+mod common;
+
+use std::{fs, io::Cursor, path::PathBuf};
+
+use common::{Capture, Fixture};
+use crafting_interpreters::{
+    dap,
+    debugger::{Console, Debugger},
+};
+use serde_json::{json, Value};
+
+fn program() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/debugger/steps.lox")
+}
+
+/// Runs the program under the console with `commands` as its input.
+/// Returns what the console printed, what the program printed and its
+/// diagnostics.
+fn console(commands: &str) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let source = fs::read_to_string(program()).unwrap();
+    let console_output = Capture::default();
+    let console = Console::new(
+        &source,
+        Cursor::new(commands.to_string()),
+        console_output.clone(),
+    );
+
+    let mut fixture = Fixture::new();
+    fixture.interpreter.debugger = Some(Debugger::new(console).stop_on_entry());
+    fixture.run_file(&program());
+
+    (
+        console_output.lines(),
+        fixture.output.lines(),
+        fixture.diagnostics.lines(),
+    )
+}
+
+#[test]
+fn console_stops_at_breakpoints_and_shows_frames() {
+    let (console, output, _) = console("b 4\nc\nbt\nv\np a + b * 10\nc\nd 4\nc\n");
+    assert_eq!(
+        console,
+        [
+            "Stopped at line 1: var total = 0;",
+            "(debug) Breakpoint at line 4.",
+            "(debug) Breakpoint at line 4: return sum;",
+            "(debug) #0 add at line 4",
+            "#1 <script> at line 7",
+            "(debug) scope 0:",
+            "  a = 0",
+            "  b = 0",
+            "  sum = 0",
+            "globals:",
+            "  add = <fn add>",
+            "  total = 0",
+            "(debug) 0",
+            "(debug) Breakpoint at line 4: return sum;",
+            "(debug) Deleted the breakpoint at line 4.",
+            "(debug) ",
+        ]
+    );
+    assert_eq!(output, ["3"]);
+}
+
+#[test]
+fn console_steps_in_over_and_out() {
+    let (console, _, _) = console("n\nn\nn\ns\ns\no\nn\nn\nq\n");
+    let stops: Vec<_> = console
+        .iter()
+        .filter_map(|line| line.split_once("Stopped at ").map(|(_, stop)| stop))
+        .collect();
+    assert_eq!(
+        stops,
+        [
+            "line 1: var total = 0;",
+            "line 2: fun add(a, b) {",
+            "line 6: for (var i = 0; i < 3; i++) {",
+            "line 7: total = add(total, i);",
+            "line 3: var sum = a + b;",
+            "line 4: return sum;",
+            "line 7: total = add(total, i);",
+            "line 7: total = add(total, i);",
+            "line 9: print total; // expect: 3",
+        ]
+    );
+}
+
+#[test]
+fn console_evaluates_in_the_current_frame() {
+    let (console, _, _) = console("b 3\nc\np a = 5\np a\np total = 10\np nope\np 1 +\nq\n");
+    assert_eq!(
+        console[3..],
+        [
+            "(debug) 5",
+            "(debug) 0",
+            "(debug) 10",
+            "(debug) Error: Undefined variable 'nope'.",
+            "(debug) Error: Expect expression",
+            "(debug) ",
+        ]
+    );
+}
+
+#[test]
+fn quitting_ends_the_program_with_an_error() {
+    let (_, output, diagnostics) = console("q\n");
+    assert!(output.is_empty());
+    assert_eq!(diagnostics, ["Stopped by the debugger."]);
+}
+
+#[test]
+fn end_of_input_runs_the_program_to_the_end() {
+    let (_, output, diagnostics) = console("");
+    assert_eq!(output, ["3"]);
+    assert!(diagnostics.is_empty());
+}
+
+/// Frames `requests` the way a client sends them.
+fn framed(requests: &[Value]) -> String {
+    requests
+        .iter()
+        .enumerate()
+        .map(|(seq, request)| {
+            let mut request = request.clone();
+            request["seq"] = json!(seq + 1);
+            request["type"] = json!("request");
+            let content = request.to_string();
+            format!("Content-Length: {}\r\n\r\n{content}", content.len())
+        })
+        .collect()
+}
+
+fn request(command: &str, arguments: Value) -> Value {
+    json!({ "command": command, "arguments": arguments })
+}
+
+/// Everything the adapter sent, in order.
+fn session(requests: &[Value]) -> Vec<Value> {
+    let output = Capture::default();
+    dap::serve(Cursor::new(framed(requests)), output.clone()).unwrap();
+    output
+        .text()
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| {
+            let (_, content) = message.split_once("\r\n\r\n").unwrap();
+            serde_json::from_str(content).unwrap()
+        })
+        .collect()
+}
+
+/// The body of the response to `command`, the `nth` one if it was sent
+/// more than once.
+fn response<'a>(messages: &'a [Value], command: &str, nth: usize) -> &'a Value {
+    let response = messages
+        .iter()
+        .filter(|message| message["type"] == "response" && message["command"] == command)
+        .nth(nth)
+        .unwrap_or_else(|| panic!("no response {nth} to {command}"));
+    assert_eq!(response["success"], true, "{response}");
+    &response["body"]
+}
+
+fn events<'a>(messages: &'a [Value], event: &str) -> Vec<&'a Value> {
+    messages
+        .iter()
+        .filter(|message| message["type"] == "event" && message["event"] == event)
+        .map(|message| &message["body"])
+        .collect()
+}
+
+#[test]
+fn adapter_debugs_a_launched_program() {
+    let program = program();
+    let messages = session(&[
+        request("initialize", json!({ "adapterID": "lox" })),
+        request("launch", json!({ "program": program, "stopOnEntry": true })),
+        request(
+            "setBreakpoints",
+            json!({ "source": { "path": program }, "breakpoints": [{ "line": 3 }] }),
+        ),
+        request("configurationDone", json!({})),
+        // Stopped on entry.
+        request("continue", json!({ "threadId": 1 })),
+        // Stopped at the breakpoint, in the first call to `add`.
+        request("stackTrace", json!({ "threadId": 1 })),
+        request("scopes", json!({ "frameId": 1 })),
+        request("variables", json!({ "variablesReference": 1 })),
+        request(
+            "evaluate",
+            json!({ "expression": "i + total", "frameId": 1 }),
+        ),
+        request("setBreakpoints", json!({ "breakpoints": [] })),
+        request("stepOut", json!({ "threadId": 1 })),
+        request("continue", json!({ "threadId": 1 })),
+        request("disconnect", json!({})),
+    ]);
+
+    let stops: Vec<_> = events(&messages, "stopped")
+        .iter()
+        .map(|stop| stop["reason"].as_str().unwrap())
+        .collect();
+    assert_eq!(stops, ["entry", "breakpoint", "step"]);
+
+    let frames = &response(&messages, "stackTrace", 0)["stackFrames"];
+    assert_eq!(frames[0]["name"], "add");
+    assert_eq!(frames[0]["line"], 3);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 7);
+
+    let scopes = &response(&messages, "scopes", 0)["scopes"];
+    let names: Vec<_> = scopes
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|scope| scope["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Locals", "Enclosing 1", "Globals"]);
+
+    // The body of the `for` loop has no variables of its own.
+    assert_eq!(response(&messages, "variables", 0)["variables"], json!([]));
+    assert_eq!(response(&messages, "evaluate", 0)["result"], "0");
+
+    let output: Vec<_> = events(&messages, "output")
+        .iter()
+        .map(|output| output["output"].as_str().unwrap())
+        .collect();
+    assert_eq!(output, ["3\n"]);
+    assert_eq!(events(&messages, "exited")[0]["exitCode"], 0);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+    response(&messages, "disconnect", 0);
+}
+
+#[test]
+fn adapter_rejects_missing_programs() {
+    let messages = session(&[
+        request("initialize", json!({})),
+        request("launch", json!({ "program": "/no/such/file.lox" })),
+    ]);
+    let launch = messages
+        .iter()
+        .find(|message| message["command"] == "launch")
+        .unwrap();
+    assert_eq!(launch["success"], false);
+    assert_eq!(launch["message"], "Could not open '/no/such/file.lox'.");
+}
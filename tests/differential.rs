@@ -0,0 +1,330 @@
+//! Differential tests: every backend has to behave exactly like the plain
+//! tree-walking interpreter, on the test suite, the examples and the
+//! benchmarks as well as on randomly generated programs.
+//!
+//! A backend is a way of configuring `Lox`. To check a new one, add it to
+//! `BACKENDS`.
+
+mod common;
+
+use std::path::Path;
+
+use common::{lox_files, Fixture};
+use crafting_interpreters::{sandbox::Capabilities, Lox};
+use proptest::{prelude::*, strategy::Union};
+
+struct Backend {
+    name: &'static str,
+    configure: fn(&mut Lox),
+}
+
+/// Backends compared against the plain tree-walker.
+const BACKENDS: &[Backend] = &[Backend {
+    name: "optimizer",
+    configure: |lox| lox.set_optimize(true),
+}];
+
+/// Everything a run of a program can be observed doing.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    output: Vec<String>,
+    diagnostics: Vec<String>,
+    exit_code: i32,
+}
+
+#[derive(Clone, Copy)]
+enum Program<'a> {
+    File(&'a Path),
+    Source(&'a str),
+}
+
+/// Runs `program` with `Lox` set up by `configure`.
+fn run(program: Program, configure: fn(&mut Lox)) -> Outcome {
+    let mut fixture = Fixture::new();
+    configure(&mut fixture.lox);
+    fixture.interpreter.capabilities = Capabilities::ALL;
+    match program {
+        Program::File(path) => fixture.run_file(path),
+        Program::Source(source) => fixture.run(source),
+    }
+
+    Outcome {
+        output: fixture.output.lines(),
+        diagnostics: fixture.diagnostics.lines(),
+        exit_code: fixture.lox.exit_code(),
+    }
+}
+
+/// How each backend's run differs from the tree-walker's.
+fn compare(program: Program) -> Vec<String> {
+    let expected = run(program, |_| ());
+    BACKENDS
+        .iter()
+        .filter_map(|backend| {
+            let actual = run(program, backend.configure);
+            (actual != expected).then(|| {
+                format!(
+                    "{}:\n  tree-walker: {expected:?}\n  {}: {actual:?}",
+                    backend.name, backend.name
+                )
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn backends_agree_on_every_program() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut failures = vec![];
+    let mut count = 0;
+    for dir in ["tests", "examples", "benches/programs"] {
+        for path in lox_files(&root.join(dir)) {
+            count += 1;
+            let differences = compare(Program::File(&path));
+            if !differences.is_empty() {
+                let name = path.strip_prefix(root).unwrap().display();
+                failures.push(format!("{name}\n{}", differences.join("\n")));
+            }
+        }
+    }
+
+    assert!(count > 0, "no programs found");
+    assert!(
+        failures.is_empty(),
+        "{} of {count} programs differ:\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
+
+/// Global variables every generated program starts with, with their
+/// initial values. Generated code mostly keeps them at these types, so most
+/// programs run to the end rather than stopping at the first type error.
+const GLOBALS: &str = "\
+var a = 1;
+var b = 2.5;
+var c = \"s\";
+var d = true;
+var e = nil;
+var xs = [1, 2, 3];
+var m = {\"k\": 1, 2: \"two\"};
+";
+
+/// Functions every generated program defines, so calls happen without
+/// risking unbounded recursion.
+const FUNCTIONS: &str = "\
+fun add(x, y) { return x + y; }
+fun pick(x, y) { if (x) return y; return -y; }
+fun counter() { var n = 0; return fun () { n += 1; return n; }; }
+var next = counter();
+";
+
+fn select(options: &[&'static str]) -> BoxedStrategy<String> {
+    prop::sample::select(options.to_vec())
+        .prop_map(str::to_string)
+        .boxed()
+}
+
+fn number() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => (0..10u32).prop_map(|n| n.to_string()),
+        1 => (0..100u32).prop_map(|n| format!("{n}.5")),
+        1 => Just("16777217".to_string()),
+    ]
+}
+
+/// Expressions that are usually numbers. Mostly constant, which the
+/// optimizer can fold, mixed with variables and calls, which it can't.
+fn numeric() -> BoxedStrategy<String> {
+    let leaf = prop_oneof![
+        6 => number(),
+        2 => select(&["a", "b"]),
+        1 => select(&["len(xs)", "xs[0]", "xs[2]", "m[\"k\"]", "next()"]),
+    ];
+    leaf.prop_recursive(4, 32, 3, |inner| {
+        // Bitwise operators need integers, so they get small ones.
+        let integer = prop_oneof![
+            (0..10u32).prop_map(|n| n.to_string()),
+            select(&["len(xs)", "xs[0]"])
+        ];
+        prop_oneof![
+            6 => (inner.clone(), select(&["+", "-", "*", "/", "%", "~/", "**"]), inner.clone())
+                .prop_map(|(left, operator, right)| format!("({left} {operator} {right})")),
+            1 => (integer.clone(), select(&["&", "|", "^", "<<", ">>"]), integer.clone())
+                .prop_map(|(left, operator, right)| format!("({left} {operator} {right})")),
+            1 => inner.clone().prop_map(|right| format!("(-{right})")),
+            1 => integer.prop_map(|right| format!("(~{right})")),
+            1 => (condition(inner.clone()), inner.clone(), inner.clone())
+                .prop_map(|(condition, then, other)| format!("({condition} ? {then} : {other})")),
+            1 => (expression_with(inner.clone()), inner.clone())
+                .prop_map(|(left, right)| format!("({left}, {right})")),
+            1 => (inner.clone(), inner.clone()).prop_map(|(x, y)| format!("add({x}, {y})")),
+            1 => (condition(inner.clone()), inner.clone())
+                .prop_map(|(x, y)| format!("pick({x}, {y})")),
+            1 => (select(&["a", "b"]), inner.clone())
+                .prop_map(|(name, value)| format!("({name} = {value})")),
+            1 => (select(&["a", "b"]), select(&["+=", "-=", "*=", "/="]), inner.clone())
+                .prop_map(|(name, operator, value)| format!("({name} {operator} {value})")),
+            1 => (select(&["a", "b", "xs[1]"]), select(&["++", "--"]), any::<bool>()).prop_map(
+                |(target, operator, postfix)| if postfix {
+                    format!("({target}{operator})")
+                } else {
+                    format!("({operator}{target})")
+                }
+            ),
+        ]
+    })
+    .boxed()
+}
+
+/// Expressions that are usually booleans, comparing `numbers`.
+fn condition(numbers: BoxedStrategy<String>) -> BoxedStrategy<String> {
+    prop_oneof![
+        2 => select(&["true", "false", "d", "nil", "e"]),
+        4 => (numbers.clone(), select(&["==", "!=", "<", "<=", ">", ">="]), numbers.clone())
+            .prop_map(|(left, operator, right)| format!("({left} {operator} {right})")),
+        1 => (select(&["!", "!!"]), numbers.clone(), numbers)
+            .prop_map(|(operator, left, right)| format!("({operator}({left} < {right}))")),
+        1 => select(&["(d = !d)", "(c == \"s\")", "has(m, \"k\")"]),
+    ]
+    .boxed()
+}
+
+/// Expressions that are usually strings.
+fn text() -> BoxedStrategy<String> {
+    let leaf = select(&["\"\"", "\"x\"", "\"ab\"", "c", "m[2]"]);
+    leaf.prop_recursive(2, 8, 2, |inner| {
+        prop_oneof![
+            (inner.clone(), inner.clone()).prop_map(|(left, right)| format!("({left} + {right})")),
+            (condition(numeric()), inner.clone(), inner.clone())
+                .prop_map(|(condition, then, other)| format!("({condition} ? {then} : {other})")),
+            inner.prop_map(|value| format!("(c = {value})")),
+        ]
+    })
+    .boxed()
+}
+
+/// Any expression, using `numbers` for the numeric ones. Now and then a
+/// type error, so failing operators are compared as well.
+fn expression_with(numbers: BoxedStrategy<String>) -> BoxedStrategy<String> {
+    prop_oneof![
+        6 => numbers.clone(),
+        2 => condition(numbers.clone()),
+        1 => select(&["\"\"", "\"x\"", "c"]),
+        1 => prop::collection::vec(numbers.clone(), 0..3)
+            .prop_map(|elements| format!("[{}]", elements.join(", "))),
+        1 => (numbers.clone(), select(&["+", "-", "<", "&"]), select(&["c", "nil", "d", "xs"]))
+            .prop_map(|(left, operator, right)| format!("({left} {operator} {right})")),
+    ]
+    .boxed()
+}
+
+fn expression() -> BoxedStrategy<String> {
+    prop_oneof![
+        4 => expression_with(numeric()),
+        1 => text(),
+    ]
+    .boxed()
+}
+
+/// Statements nested at most `depth` deep, inside `loops` loops. Loops
+/// always count up to a small bound with a counter nothing else assigns,
+/// so every program terminates.
+fn statement(depth: u32, loops: u32) -> BoxedStrategy<String> {
+    let mut statements = vec![
+        (
+            4,
+            expression()
+                .prop_map(|expr| format!("print {expr};"))
+                .boxed(),
+        ),
+        (1, expression().prop_map(|expr| format!("{expr};")).boxed()),
+        (
+            2,
+            prop_oneof![
+                (select(&["a", "b", "xs[0]"]), numeric()),
+                (Just("c".to_string()), text()),
+                (Just("d".to_string()), condition(numeric())),
+                (select(&["e", "m[\"k\"]", "m[3]"]), expression()),
+            ]
+            .prop_map(|(name, value)| format!("{name} = {value};"))
+            .boxed(),
+        ),
+    ];
+    if loops > 0 {
+        statements.push((
+            1,
+            (condition(numeric()), select(&["break", "continue"]))
+                .prop_map(|(condition, jump)| format!("if ({condition}) {jump};"))
+                .boxed(),
+        ));
+    }
+    if depth == 0 {
+        return Union::new_weighted(statements).boxed();
+    }
+
+    let block = |loops| {
+        prop::collection::vec(statement(depth - 1, loops), 0..4)
+            .prop_map(|statements| format!("{{\n{}\n}}", statements.join("\n")))
+    };
+    statements.push((
+        1,
+        (
+            condition(numeric()),
+            block(loops),
+            prop::option::of(block(loops)),
+        )
+            .prop_map(|(condition, then, other)| match other {
+                Some(other) => format!("if ({condition}) {then} else {other}"),
+                None => format!("if ({condition}) {then}"),
+            })
+            .boxed(),
+    ));
+    statements.push((
+        1,
+        (0..4u32, block(loops + 1))
+            .prop_map(move |(bound, body)| {
+                let i = format!("i{loops}");
+                format!("for (var {i} = 0; {i} < {bound}; {i}++) {body}")
+            })
+            .boxed(),
+    ));
+    statements.push((
+        1,
+        (
+            select(&["false", "nil", "!true", "1 > 2", "!!0 == false"]),
+            block(loops + 1),
+        )
+            .prop_map(|(condition, body)| format!("while ({condition}) {body}"))
+            .boxed(),
+    ));
+    statements.push((
+        1,
+        (expression(), block(loops))
+            .prop_map(|(value, body)| format!("{{\nvar local = {value};\nprint local;\n{body}\n}}"))
+            .boxed(),
+    ));
+    statements.push((
+        1,
+        (block(loops), block(loops))
+            .prop_map(|(body, finally)| {
+                format!("try {body} catch (error) {{\nprint error;\n}} finally {finally}")
+            })
+            .boxed(),
+    ));
+    Union::new_weighted(statements).boxed()
+}
+
+/// A random program that uses most of the language and always terminates.
+fn program() -> impl Strategy<Value = String> {
+    prop::collection::vec(statement(2, 0), 1..12)
+        .prop_map(|statements| format!("{GLOBALS}{FUNCTIONS}{}\n", statements.join("\n")))
+}
+
+proptest! {
+    #[test]
+    fn backends_agree_on_random_programs(source in program()) {
+        let differences = compare(Program::Source(&source));
+        prop_assert!(differences.is_empty(), "{}\n\n{source}", differences.join("\n"));
+    }
+}
@@ -0,0 +1,288 @@
+//! Talks to the language server through an in-memory connection, the way
+//! an editor would over stdio.
+
+use std::thread::{self, JoinHandle};
+
+use crafting_interpreters::lsp;
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Initialize, References,
+        Request as LspRequest, Shutdown,
+    },
+    CompletionParams, CompletionResponse, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
+    GotoDefinitionResponse, HoverContents, HoverParams, InitializeParams, Position,
+    PublishDiagnosticsParams, Range, ReferenceContext, ReferenceParams,
+    TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, Uri, VersionedTextDocumentIdentifier,
+};
+
+const SOURCE: &str = "\
+var total = 0;
+fun add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+total = add(total, 1);
+print total + len([1]);
+";
+
+struct Client {
+    connection: Connection,
+    server: Option<JoinHandle<()>>,
+    next_id: i32,
+    uri: Uri,
+}
+
+impl Client {
+    /// Starts a server, initializes it and opens `SOURCE` in it.
+    fn start() -> Self {
+        let (server, connection) = Connection::memory();
+        let server = thread::spawn(move || lsp::serve(&server).unwrap());
+        let mut client = Self {
+            connection,
+            server: Some(server),
+            next_id: 0,
+            uri: "file:///test.lox".parse().unwrap(),
+        };
+        client.request::<Initialize>(InitializeParams::default());
+        client.notify::<Initialized>(lsp_types::InitializedParams {});
+        client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                client.uri.clone(),
+                "lox".to_string(),
+                1,
+                SOURCE.to_string(),
+            ),
+        });
+        client
+    }
+
+    fn request<R: LspRequest>(&mut self, params: R::Params) -> R::Result {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), R::METHOD.to_string(), params);
+        self.connection.sender.send(request.into()).unwrap();
+        loop {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(response) if response.id == id => {
+                    assert!(response.error.is_none(), "{:?}", response.error);
+                    return serde_json::from_value(response.result.unwrap()).unwrap();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn notify<N: LspNotification>(&self, params: N::Params) {
+        let notification = Notification::new(N::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into()).unwrap();
+    }
+
+    /// Waits for the next diagnostics the server publishes.
+    fn diagnostics(&self) -> PublishDiagnosticsParams {
+        loop {
+            if let Message::Notification(notification) = self.connection.receiver.recv().unwrap() {
+                if notification.method == PublishDiagnostics::METHOD {
+                    return serde_json::from_value(notification.params).unwrap();
+                }
+            }
+        }
+    }
+
+    fn position(&self, line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(self.uri.clone()),
+            Position::new(line, character),
+        )
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.request::<Shutdown>(());
+        self.notify::<Exit>(());
+        self.server.take().unwrap().join().unwrap();
+    }
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+#[test]
+fn publishes_diagnostics_when_documents_change() {
+    let client = Client::start();
+    let opened = client.diagnostics();
+    assert_eq!(opened.uri, client.uri);
+    assert!(opened
+        .diagnostics
+        .iter()
+        .all(|d| d.severity != Some(DiagnosticSeverity::ERROR)));
+
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(client.uri.clone(), 2),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "var x = 1;\nprint x +;\n".to_string(),
+        }],
+    });
+    let changed = client.diagnostics();
+    assert_eq!(changed.diagnostics.len(), 1);
+    let error = &changed.diagnostics[0];
+    assert_eq!(error.severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(error.range, range(1, 9, 10));
+    assert_eq!(error.message, "Expect expression");
+}
+
+#[test]
+fn reports_deep_nesting_instead_of_crashing() {
+    let client = Client::start();
+    client.diagnostics();
+    let depth = 50_000;
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(client.uri.clone(), 2),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+        }],
+    });
+    let changed = client.diagnostics();
+    assert_eq!(changed.diagnostics.len(), 1);
+    assert_eq!(
+        changed.diagnostics[0].message,
+        "Expression nested too deeply."
+    );
+}
+
+#[test]
+fn goes_to_definitions() {
+    let mut client = Client::start();
+    let definition = |client: &mut Client, line, character| match client.request::<GotoDefinition>(
+        GotoDefinitionParams {
+            text_document_position_params: client.position(line, character),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        },
+    ) {
+        Some(GotoDefinitionResponse::Scalar(location)) => Some(location.range),
+        None => None,
+        response => panic!("unexpected response {response:?}"),
+    };
+
+    // `total` in `print total`, and at its end.
+    assert_eq!(definition(&mut client, 6, 6), Some(range(0, 4, 9)));
+    assert_eq!(definition(&mut client, 6, 11), Some(range(0, 4, 9)));
+    // `b` in `a + b` is the parameter.
+    assert_eq!(definition(&mut client, 2, 18), Some(range(1, 11, 12)));
+    // `add` in the call.
+    assert_eq!(definition(&mut client, 5, 8), Some(range(1, 4, 7)));
+    // Built-ins and keywords have no definition.
+    assert_eq!(definition(&mut client, 6, 14), None);
+    assert_eq!(definition(&mut client, 6, 0), None);
+}
+
+#[test]
+fn finds_references() {
+    let mut client = Client::start();
+    let mut references = |include_declaration| {
+        let locations = client
+            .request::<References>(ReferenceParams {
+                text_document_position: client.position(0, 5),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: ReferenceContext {
+                    include_declaration,
+                },
+            })
+            .unwrap();
+        locations
+            .into_iter()
+            .map(|location| location.range)
+            .collect::<Vec<_>>()
+    };
+
+    let uses = vec![range(5, 0, 5), range(5, 12, 17), range(6, 6, 11)];
+    assert_eq!(references(false), uses);
+    let mut all = vec![range(0, 4, 9)];
+    all.extend(uses);
+    assert_eq!(references(true), all);
+}
+
+#[test]
+fn hovers_over_names() {
+    let mut client = Client::start();
+    let mut hover = |line, character| {
+        let hover = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: client.position(line, character),
+            work_done_progress_params: Default::default(),
+        })?;
+        match hover.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            contents => panic!("unexpected contents {contents:?}"),
+        }
+    };
+
+    assert_eq!(
+        hover(2, 14).unwrap(),
+        "```lox\na\n```\nParameter declared on line 2."
+    );
+    assert_eq!(
+        hover(5, 9).unwrap(),
+        "```lox\nfun add(a, b)\n```\nFunction declared on line 2."
+    );
+    assert!(hover(6, 15).unwrap().contains("Built-in function"));
+    assert_eq!(hover(3, 4), None);
+}
+
+#[test]
+fn lists_document_symbols() {
+    let mut client = Client::start();
+    let Some(DocumentSymbolResponse::Nested(symbols)) =
+        client.request::<DocumentSymbolRequest>(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(client.uri.clone()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+    else {
+        panic!("expected nested symbols");
+    };
+
+    let names = |symbols: &[lsp_types::DocumentSymbol]| {
+        symbols
+            .iter()
+            .map(|symbol| symbol.name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&symbols), ["total", "add"]);
+    assert_eq!(
+        names(symbols[1].children.as_ref().unwrap()),
+        ["a", "b", "sum"]
+    );
+}
+
+#[test]
+fn completes_keywords_and_names() {
+    let mut client = Client::start();
+    let Some(CompletionResponse::Array(items)) = client.request::<Completion>(CompletionParams {
+        text_document_position: client.position(6, 0),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    }) else {
+        panic!("expected a list of completions");
+    };
+
+    let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+    for label in ["total", "add", "sum", "len", "while", "print"] {
+        assert!(labels.contains(&label), "missing {label} in {labels:?}");
+    }
+    assert_eq!(labels.iter().filter(|&&label| label == "total").count(), 1);
+}
@@ -0,0 +1,591 @@
+//! Property tests for the front end: random valid programs survive being
+//! printed and parsed again, and no input makes the scanner or parser panic.
+
+mod common;
+
+use std::{fs, io, path::Path, rc::Rc};
+
+use common::lox_files;
+
+use crafting_interpreters::{
+    expr::{Expr, Slot},
+    parser::Parser,
+    printer::Printer,
+    scanner::Scanner,
+    stmt::{FunctionDecl, Stmt},
+    symbol::Symbol,
+    token::{LiteralVal, Token, TokenType},
+    Lox,
+};
+use proptest::{prelude::*, strategy::Union};
+
+/// A token like the scanner makes it. Only the position is made up.
+fn token(token_type: TokenType, lexeme: &str) -> Token {
+    let symbol = match token_type {
+        TokenType::Identifier => Symbol::intern(lexeme),
+        _ => Symbol::default(),
+    };
+    Token {
+        token_type,
+        lexeme: lexeme.to_string(),
+        symbol,
+        literal: LiteralVal::Nil,
+        line: 1,
+        column: 1,
+    }
+}
+
+fn identifier() -> impl Strategy<Value = Token> {
+    prop::sample::select(vec!["a", "b", "c", "value", "list_2", "_x"])
+        .prop_map(|name| token(TokenType::Identifier, name))
+}
+
+fn type_name() -> impl Strategy<Value = Option<Token>> {
+    prop::option::of(prop::sample::select(vec![
+        (TokenType::Identifier, "number"),
+        (TokenType::Identifier, "string"),
+        (TokenType::Identifier, "bool"),
+        (TokenType::Nil, "nil"),
+        (TokenType::Fun, "fun"),
+    ]))
+    .prop_map(|name| name.map(|(token_type, lexeme)| token(token_type, lexeme)))
+}
+
+fn literal() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        any::<f32>()
+            .prop_filter("no literal syntax", |n| n.is_finite()
+                && n.is_sign_positive())
+            .prop_map(LiteralVal::Number),
+        (0..1000u32).prop_map(|n| LiteralVal::Number(n as f32)),
+        "[a-z ]{0,8}".prop_map(LiteralVal::Str),
+        any::<bool>().prop_map(LiteralVal::Boolean),
+        Just(LiteralVal::Nil),
+    ]
+    .prop_map(Expr::Literal)
+}
+
+/// Whether `expr` parses the same wherever an operand is expected.
+fn is_atomic(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Call { .. }
+            | Expr::Get(..)
+            | Expr::Grouping(_)
+            | Expr::Index { .. }
+            | Expr::Lambda(_)
+            | Expr::List(_)
+            | Expr::Literal(_)
+            | Expr::Map(..)
+            | Expr::Variable(..)
+    )
+}
+
+/// Parenthesizes `expr` unless it's atomic, so the generator doesn't have
+/// to know about precedence.
+fn operand(expr: Expr) -> Box<Expr> {
+    if is_atomic(&expr) {
+        Box::new(expr)
+    } else {
+        Box::new(Expr::Grouping(Box::new(expr)))
+    }
+}
+
+/// Whether printing `expr` starts with `{`, which would make it a block at
+/// the start of a statement.
+fn starts_with_brace(expr: &Expr) -> bool {
+    match expr {
+        Expr::Map(..) => true,
+        Expr::Binary { left: expr, .. }
+        | Expr::Call { callee: expr, .. }
+        | Expr::Conditional {
+            condition: expr, ..
+        }
+        | Expr::Get(expr, _)
+        | Expr::Index { object: expr, .. }
+        | Expr::IndexSet { object: expr, .. }
+        | Expr::Set(expr, ..) => starts_with_brace(expr),
+        Expr::Compound {
+            target,
+            operator,
+            postfix,
+            ..
+        } => (*postfix || operator.lexeme.ends_with('=')) && starts_with_brace(target),
+        _ => false,
+    }
+}
+
+fn print(expr: Expr) -> Stmt {
+    Stmt::Print(token(TokenType::Print, "print"), expr)
+}
+
+fn expression_statement(expr: Expr) -> Stmt {
+    if starts_with_brace(&expr) {
+        Stmt::Expression(Expr::Grouping(Box::new(expr)))
+    } else {
+        Stmt::Expression(expr)
+    }
+}
+
+fn binary_operator() -> impl Strategy<Value = Token> {
+    prop::sample::select(vec![
+        (TokenType::Plus, "+"),
+        (TokenType::Minus, "-"),
+        (TokenType::Star, "*"),
+        (TokenType::Slash, "/"),
+        (TokenType::Percent, "%"),
+        (TokenType::TildeSlash, "~/"),
+        (TokenType::StarStar, "**"),
+        (TokenType::EqualEqual, "=="),
+        (TokenType::BangEqual, "!="),
+        (TokenType::Less, "<"),
+        (TokenType::LessEqual, "<="),
+        (TokenType::Greater, ">"),
+        (TokenType::GreaterEqual, ">="),
+        (TokenType::Ampersand, "&"),
+        (TokenType::Pipe, "|"),
+        (TokenType::Caret, "^"),
+        (TokenType::LessLess, "<<"),
+        (TokenType::GreaterGreater, ">>"),
+        (TokenType::Comma, ","),
+    ])
+    .prop_map(|(token_type, lexeme)| token(token_type, lexeme))
+}
+
+/// Compound operators with the plain operator's type, like the parser
+/// makes them.
+fn compound_operator() -> impl Strategy<Value = Token> {
+    prop::sample::select(vec![
+        (TokenType::Plus, "+="),
+        (TokenType::Minus, "-="),
+        (TokenType::Star, "*="),
+        (TokenType::Slash, "/="),
+        (TokenType::Plus, "++"),
+        (TokenType::Minus, "--"),
+    ])
+    .prop_map(|(token_type, lexeme)| token(token_type, lexeme))
+}
+
+fn expression() -> impl Strategy<Value = Expr> {
+    let leaf = prop_oneof![
+        literal(),
+        identifier().prop_map(|name| Expr::Variable(name, Slot::default())),
+    ];
+    leaf.prop_recursive(4, 48, 4, |inner| {
+        let target = prop_oneof![
+            identifier().prop_map(|name| Expr::Variable(name, Slot::default())),
+            (inner.clone(), identifier())
+                .prop_map(|(object, name)| Expr::Get(operand(object), name)),
+            (inner.clone(), inner.clone()).prop_map(|(object, index)| Expr::Index {
+                object: operand(object),
+                bracket: token(TokenType::RightBracket, "]"),
+                index: Box::new(index),
+            }),
+        ];
+        let lambda_body = prop::collection::vec(
+            prop_oneof![
+                inner.clone().prop_map(print),
+                prop::option::of(inner.clone())
+                    .prop_map(|value| Stmt::Return(token(TokenType::Return, "return"), value)),
+            ],
+            0..3,
+        );
+
+        prop_oneof![
+            (inner.clone(), binary_operator(), inner.clone()).prop_map(
+                |(left, operator, right)| Expr::Binary {
+                    left: operand(left),
+                    operator,
+                    right: operand(right),
+                }
+            ),
+            (
+                prop::sample::select(vec![
+                    (TokenType::Bang, "!"),
+                    (TokenType::Minus, "-"),
+                    (TokenType::Tilde, "~"),
+                ]),
+                inner.clone()
+            )
+                .prop_map(|((token_type, lexeme), right)| Expr::Unary {
+                    operator: token(token_type, lexeme),
+                    right: operand(right),
+                }),
+            inner
+                .clone()
+                .prop_map(|expr| Expr::Grouping(Box::new(expr))),
+            (inner.clone(), prop::collection::vec(inner.clone(), 0..3)).prop_map(
+                |(callee, arguments)| Expr::Call {
+                    callee: operand(callee),
+                    paren: token(TokenType::RightParen, ")"),
+                    arguments: arguments.into_iter().map(|a| *operand(a)).collect(),
+                }
+            ),
+            (inner.clone(), identifier())
+                .prop_map(|(object, name)| Expr::Get(operand(object), name)),
+            (inner.clone(), inner.clone()).prop_map(|(object, index)| Expr::Index {
+                object: operand(object),
+                bracket: token(TokenType::RightBracket, "]"),
+                index: Box::new(index),
+            }),
+            prop::collection::vec(inner.clone(), 0..3).prop_map(|elements| Expr::List(
+                elements.into_iter().map(|e| *operand(e)).collect()
+            )),
+            prop::collection::vec((inner.clone(), inner.clone()), 0..3).prop_map(|entries| {
+                Expr::Map(
+                    token(TokenType::LeftBrace, "{"),
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (*operand(key), *operand(value)))
+                        .collect(),
+                )
+            }),
+            (inner.clone(), inner.clone(), inner.clone()).prop_map(
+                |(condition, then_branch, else_branch)| Expr::Conditional {
+                    condition: operand(condition),
+                    then_branch: operand(then_branch),
+                    else_branch: operand(else_branch),
+                }
+            ),
+            (identifier(), inner.clone()).prop_map(|(name, value)| Expr::Assign(
+                name,
+                operand(value),
+                Slot::default()
+            )),
+            (inner.clone(), identifier(), inner.clone()).prop_map(|(object, name, value)| {
+                Expr::Set(operand(object), name, operand(value))
+            }),
+            (inner.clone(), inner.clone(), inner.clone()).prop_map(|(object, index, value)| {
+                Expr::IndexSet {
+                    object: operand(object),
+                    bracket: token(TokenType::RightBracket, "]"),
+                    index: Box::new(index),
+                    value: operand(value),
+                }
+            }),
+            (target, compound_operator(), inner.clone(), any::<bool>()).prop_map(
+                |(target, operator, value, postfix)| {
+                    let (value, postfix) = if operator.lexeme.ends_with('=') {
+                        (operand(value), false)
+                    } else {
+                        (Box::new(Expr::Literal(LiteralVal::Number(1.))), postfix)
+                    };
+                    Expr::Compound {
+                        target: Box::new(target),
+                        operator,
+                        value,
+                        postfix,
+                    }
+                }
+            ),
+            (
+                prop::collection::vec((identifier(), type_name()), 0..3),
+                type_name(),
+                lambda_body
+            )
+                .prop_map(|(params, return_type, body)| {
+                    let (params, param_types) = params.into_iter().unzip();
+                    Expr::Lambda(Rc::new(FunctionDecl {
+                        name: None,
+                        params,
+                        param_types,
+                        return_type,
+                        body,
+                    }))
+                }),
+        ]
+    })
+}
+
+/// Where a statement is, which decides whether `break`, `continue` and
+/// `return` are allowed in it.
+#[derive(Clone, Copy)]
+struct Context {
+    in_loop: bool,
+    in_function: bool,
+}
+
+/// Statements that aren't declarations, nested at most `depth` deep.
+fn statement(depth: u32, context: Context) -> BoxedStrategy<Stmt> {
+    let mut statements = vec![
+        expression().prop_map(print).boxed(),
+        expression().prop_map(expression_statement).boxed(),
+        expression()
+            .prop_map(|value| Stmt::Throw(token(TokenType::Throw, "throw"), value))
+            .boxed(),
+    ];
+    if context.in_loop {
+        statements.push(Just(Stmt::Break(token(TokenType::Break, "break"))).boxed());
+        statements.push(Just(Stmt::Continue(token(TokenType::Continue, "continue"))).boxed());
+    }
+    if context.in_function {
+        statements.push(
+            prop::option::of(expression())
+                .prop_map(|value| Stmt::Return(token(TokenType::Return, "return"), value))
+                .boxed(),
+        );
+    }
+    if depth == 0 {
+        return Union::new(statements).boxed();
+    }
+
+    let in_loop = Context {
+        in_loop: true,
+        ..context
+    };
+    let statements_in = || prop::collection::vec(declaration(depth - 1, context), 0..4);
+    let block = statements_in().prop_map(Stmt::Block);
+    statements.push(block.clone().boxed());
+    statements.push(
+        (
+            statements_in(),
+            prop::option::of((identifier(), statements_in())),
+            prop::option::of(statements_in()),
+        )
+            .prop_map(|(body, catch, finally)| {
+                // A `try` needs a handler or a `finally` block.
+                let finally = match (&catch, finally) {
+                    (None, None) => Some(vec![]),
+                    (_, finally) => finally,
+                };
+                Stmt::Try {
+                    keyword: token(TokenType::Try, "try"),
+                    body,
+                    catch,
+                    finally,
+                }
+            })
+            .boxed(),
+    );
+    statements.push(
+        (
+            expression(),
+            statement(depth - 1, context),
+            // A block, so the `else` can't belong to an `if` inside it.
+            prop::option::of(statement(depth - 1, context)),
+            block,
+        )
+            .prop_map(|(condition, then_branch, else_branch, then_block)| {
+                let (then_branch, else_branch) = match else_branch {
+                    Some(else_branch) => (then_block, Some(Box::new(else_branch))),
+                    None => (then_branch, None),
+                };
+                Stmt::If {
+                    keyword: token(TokenType::If, "if"),
+                    condition,
+                    then_branch: Box::new(then_branch),
+                    else_branch,
+                }
+            })
+            .boxed(),
+    );
+    statements.push(
+        (expression(), statement(depth - 1, in_loop))
+            .prop_map(|(condition, body)| Stmt::While {
+                keyword: token(TokenType::While, "while"),
+                condition,
+                body: Box::new(body),
+                increment: None,
+            })
+            .boxed(),
+    );
+    let initializer = prop_oneof![
+        (identifier(), type_name(), prop::option::of(expression()))
+            .prop_map(|(name, var_type, initializer)| Stmt::Var(name, var_type, initializer)),
+        expression().prop_map(expression_statement),
+    ];
+    statements.push(
+        (
+            prop::option::of(initializer),
+            expression(),
+            prop::option::of(expression()),
+            statement(depth - 1, in_loop),
+        )
+            .prop_map(|(initializer, condition, increment, body)| {
+                let while_loop = Stmt::While {
+                    keyword: token(TokenType::For, "for"),
+                    condition,
+                    body: Box::new(body),
+                    increment,
+                };
+                match initializer {
+                    Some(initializer) => Stmt::Block(vec![initializer, while_loop]),
+                    None => while_loop,
+                }
+            })
+            .boxed(),
+    );
+    Union::new(statements).boxed()
+}
+
+/// Any statement, including the ones only allowed directly in a block.
+fn declaration(depth: u32, context: Context) -> BoxedStrategy<Stmt> {
+    let function_body = Context {
+        in_loop: false,
+        in_function: true,
+    };
+    // Mostly statements, which is where the nesting happens.
+    let mut declarations = vec![
+        (4, statement(depth, context)),
+        (
+            1,
+            (identifier(), type_name(), prop::option::of(expression()))
+                .prop_map(|(name, var_type, initializer)| Stmt::Var(name, var_type, initializer))
+                .boxed(),
+        ),
+        (
+            1,
+            (identifier(), prop::option::of(identifier()))
+                .prop_map(|(name, path)| {
+                    let path = format!("{}.lox", path.as_ref().unwrap_or(&name).lexeme);
+                    let path = Token {
+                        literal: LiteralVal::Str(path.clone()),
+                        ..token(TokenType::String, &format!("\"{path}\""))
+                    };
+                    Stmt::Import(path, name)
+                })
+                .boxed(),
+        ),
+    ];
+    if depth > 0 {
+        declarations.push((
+            1,
+            (
+                identifier(),
+                prop::collection::vec((identifier(), type_name()), 0..3),
+                type_name(),
+                prop::collection::vec(declaration(depth - 1, function_body), 0..4),
+            )
+                .prop_map(|(name, params, return_type, body)| {
+                    let (params, param_types) = params.into_iter().unzip();
+                    Stmt::Function(Rc::new(FunctionDecl {
+                        name: Some(name),
+                        params,
+                        param_types,
+                        return_type,
+                        body,
+                    }))
+                })
+                .boxed(),
+        ));
+    }
+    Union::new_weighted(declarations).boxed()
+}
+
+fn program() -> impl Strategy<Value = Vec<Stmt>> {
+    let top_level = Context {
+        in_loop: false,
+        in_function: false,
+    };
+    prop::collection::vec(declaration(3, top_level), 1..8)
+}
+
+/// Statements parsed from `source` and whether there were errors.
+fn parse(source: &str) -> (Vec<Stmt>, bool) {
+    let mut lox = Lox::default();
+    lox.set_diagnostics(io::sink());
+    let tokens = Scanner::new(source.to_string()).scan_tokens(&mut lox);
+    let statements = Parser::new(tokens).parse(&mut lox);
+    (statements, lox.had_error())
+}
+
+/// Source that is mostly made of Lox tokens, to get further into the
+/// parser than random characters do.
+fn token_soup() -> impl Strategy<Value = String> {
+    let lexemes = prop_oneof![
+        prop::sample::select(vec![
+            "(", ")", "{", "}", "[", "]", ",", ".", ";", ":", "?", "-", "+", "*", "/", "=", "==",
+            "!", "<", "++", "+=", "**", "~/", "var", "fun", "if", "else", "for", "while", "print",
+            "return", "break", "continue", "import", "as", "nil", "true", "x", "1", "2.5", "\"s\"",
+            "\"", "/*", "*/", "//", "\n",
+        ])
+        .prop_map(str::to_string),
+        "\\PC{0,3}",
+    ];
+    prop::collection::vec(lexemes, 0..40).prop_map(|lexemes| lexemes.join(" "))
+}
+
+/// Every test program that parses prints back to itself, which covers the
+/// desugarings the generator doesn't make.
+#[test]
+fn test_programs_parse_back() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    for path in lox_files(&root) {
+        let (statements, had_error) = parse(&fs::read_to_string(&path).unwrap());
+        if had_error {
+            continue;
+        }
+        let source = Printer::new().print(&statements);
+        let (parsed, had_error) = parse(&source);
+        assert!(
+            !had_error,
+            "{} printed with errors:\n{source}",
+            path.display()
+        );
+        assert!(
+            parsed == statements,
+            "{} printed as:\n{source}",
+            path.display()
+        );
+    }
+}
+
+/// Statements and expressions nested `depth` levels deep in different ways.
+fn nested(depth: usize) -> [String; 7] {
+    [
+        format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+        format!("print {}1;", "- ".repeat(depth)),
+        format!("print 1{};", " + 1".repeat(depth)),
+        format!("var a; {}1;", "a = ".repeat(depth)),
+        format!("print {}1;", "true ? 1 : ".repeat(depth)),
+        format!("f{};", "()".repeat(depth)),
+        format!("{}{}", "{".repeat(depth), "}".repeat(depth)),
+    ]
+}
+
+/// Nesting deep enough to overflow the stack of the passes after the parser
+/// is a syntax error instead, reported once.
+#[test]
+fn deep_nesting_is_a_syntax_error() {
+    for source in nested(50_000) {
+        let mut lox = Lox::default();
+        lox.set_diagnostics(io::sink());
+        let tokens = Scanner::new(source).scan_tokens(&mut lox);
+        Parser::new(tokens).parse(&mut lox);
+        let messages: Vec<_> = lox.reported().iter().map(|d| d.message.as_str()).collect();
+        assert!(
+            matches!(
+                messages[..],
+                ["Expression nested too deeply." | "Statement nested too deeply."]
+            ),
+            "{messages:?}"
+        );
+    }
+
+    for source in nested(150) {
+        let (statements, had_error) = parse(&source);
+        assert!(!had_error, "{source}");
+        let (parsed, _) = parse(&Printer::new().print(&statements));
+        assert!(parsed == statements, "{source}");
+    }
+}
+
+proptest! {
+    #[test]
+    fn printed_programs_parse_back(program in program()) {
+        let source = Printer::new().print(&program);
+        let (parsed, had_error) = parse(&source);
+        prop_assert!(!had_error, "errors in:\n{source}");
+        prop_assert_eq!(parsed, program, "printed as:\n{}", source);
+    }
+
+    #[test]
+    fn scanner_and_parser_never_panic_on_text(source in "\\PC*") {
+        parse(&source);
+    }
+
+    #[test]
+    fn scanner_and_parser_never_panic_on_tokens(source in token_soup()) {
+        parse(&source);
+    }
+}
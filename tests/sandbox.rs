@@ -0,0 +1,281 @@
+//! Runs programs under limits, with and without capabilities, and cancels
+//! them, the way a service running untrusted scripts would.
+
+mod common;
+
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::Fixture;
+use crafting_interpreters::sandbox::{Capabilities, Limits};
+
+/// Runs `source` in `fixture`, returning what it printed and its
+/// diagnostics.
+fn run_in(mut fixture: Fixture, source: &str) -> (Vec<String>, Vec<String>) {
+    fixture.run(source);
+    (fixture.output.lines(), fixture.diagnostics.lines())
+}
+
+fn run_with(limits: Limits, source: &str) -> (Vec<String>, Vec<String>) {
+    let mut fixture = Fixture::new();
+    fixture.interpreter.limits = limits;
+    run_in(fixture, source)
+}
+
+#[test]
+fn side_effects_need_capabilities() {
+    let (_, diagnostics) = run_in(Fixture::new(), "import \"lib.lox\" as lib;");
+    assert_eq!(
+        diagnostics,
+        [
+            "Importing modules needs the filesystem capability.",
+            "[line 1;token \"lib.lox\"]"
+        ]
+    );
+
+    let (output, _) = run_in(
+        Fixture::new(),
+        "
+        try { readFile(\"secret\"); } catch (e) { print e[\"message\"]; }
+        try { writeFile(\"secret\", \"\"); } catch (e) { print e[\"message\"]; }
+        try { getEnv(\"HOME\"); } catch (e) { print e[\"message\"]; }
+        ",
+    );
+    assert_eq!(
+        output,
+        [
+            "'readFile' needs the filesystem capability.",
+            "'writeFile' needs the filesystem capability.",
+            "'getEnv' needs the environment capability.",
+        ]
+    );
+}
+
+#[test]
+fn granted_capabilities_reach_files_and_the_environment() {
+    let path = std::env::temp_dir().join(format!("lox-sandbox-{}.txt", std::process::id()));
+    let mut fixture = Fixture::new();
+    fixture.interpreter.capabilities = Capabilities::ALL;
+    let (output, diagnostics) = run_in(
+        fixture,
+        &format!(
+            "
+            writeFile({path:?}, \"written\");
+            print readFile({path:?});
+            print getEnv(\"CARGO_PKG_NAME\");
+            print getEnv(\"LOX_SURELY_NOT_SET\");
+            ",
+        ),
+    );
+    std::fs::remove_file(path).unwrap();
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    assert_eq!(output, ["written", env!("CARGO_PKG_NAME"), "nil"]);
+}
+
+#[test]
+fn step_limit_ends_the_program_past_any_handler() {
+    let limits = Limits {
+        steps: Some(100),
+        ..Limits::default()
+    };
+    let (output, diagnostics) = run_with(
+        limits,
+        "
+        print \"start\";
+        try {
+            while (true) {}
+        } catch (_) {
+            print \"caught\";
+        } finally {
+            print \"finally\";
+        }
+        ",
+    );
+    assert_eq!(output, ["start"]);
+    assert_eq!(diagnostics, ["Exceeded the limit of 100 steps."]);
+}
+
+#[test]
+fn limits_count_from_the_start_of_each_run() {
+    let mut fixture = Fixture::new();
+    fixture.interpreter.limits.steps = Some(10);
+    for _ in 0..3 {
+        fixture.run("for (var i = 0; i < 3; i++) print i;");
+    }
+    assert!(
+        fixture.diagnostics.lines().is_empty(),
+        "{:?}",
+        fixture.diagnostics.lines()
+    );
+    assert_eq!(fixture.output.lines(), ["0", "1", "2"].repeat(3));
+}
+
+#[test]
+fn time_limit_stops_endless_loops() {
+    let limits = Limits {
+        time: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    };
+    let start = Instant::now();
+    let (_, diagnostics) = run_with(limits, "while (true) {}");
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(diagnostics, ["Exceeded the time limit of 50ms."]);
+}
+
+#[test]
+fn cancelling_from_another_thread_stops_the_program() {
+    let mut fixture = Fixture::new();
+    let cancel = Arc::clone(&fixture.interpreter.cancel);
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        cancel.store(true, Ordering::Relaxed);
+    });
+
+    fixture.run("var count = 0; try { while (true) count++; } finally { print \"finally\"; }");
+    canceller.join().unwrap();
+    assert!(fixture.output.lines().is_empty());
+    assert_eq!(fixture.diagnostics.lines(), ["Interrupted"]);
+
+    // The flag is cleared and the globals are still there for the next run.
+    assert!(!fixture.interpreter.cancel.load(Ordering::Relaxed));
+    fixture.run("print count > 0;");
+    assert_eq!(fixture.output.lines(), ["true"]);
+}
+
+#[test]
+fn frame_limit_is_configurable() {
+    let limits = Limits {
+        frames: 10,
+        ..Limits::default()
+    };
+    let (output, diagnostics) = run_with(
+        limits,
+        "
+        var depth = 0;
+        fun recurse() { depth = depth + 1; recurse(); }
+        try { recurse(); } catch (e) { print e[\"message\"]; }
+        print depth;
+        ",
+    );
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    // The script itself takes up a frame.
+    assert_eq!(output, ["Stack overflow.", "9"]);
+}
+
+#[test]
+fn allocation_limit_counts_strings_and_collections() {
+    let limits = || Limits {
+        allocation: Some(1000),
+        ..Limits::default()
+    };
+
+    let (output, diagnostics) = run_with(
+        limits(),
+        "
+        var s = \"ab\";
+        while (true) { s = s + s; print len(s); }
+        ",
+    );
+    assert_eq!(output, ["4", "8", "16", "32", "64", "128", "256"]);
+    assert_eq!(
+        diagnostics,
+        [
+            "Exceeded the allocation limit of 1000 bytes.",
+            "[line 3;token +]"
+        ]
+    );
+
+    let (_, diagnostics) = run_with(limits(), "var xs = []; while (true) push(xs, 1);");
+    assert_eq!(
+        diagnostics[0],
+        "Exceeded the allocation limit of 1000 bytes."
+    );
+
+    let (_, diagnostics) = run_with(limits(), "var m = {}; for (var i = 0; ; i++) m[i] = i;");
+    assert_eq!(
+        diagnostics[0],
+        "Exceeded the allocation limit of 1000 bytes."
+    );
+
+    // Assigning to a key that's already there takes no more room.
+    let (output, diagnostics) = run_with(
+        limits(),
+        "var m = {}; for (var i = 0; i < 100; i++) m[i % 10] = i; print len(m);",
+    );
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    assert_eq!(output, ["10"]);
+}
+
+#[test]
+fn files_over_the_allocation_limit_are_not_read() {
+    let path = std::env::temp_dir().join(format!("lox-sandbox-{}-large.txt", std::process::id()));
+    std::fs::write(&path, "x".repeat(10_000)).unwrap();
+    let mut fixture = Fixture::new();
+    fixture.interpreter.capabilities = Capabilities::ALL;
+    fixture.interpreter.limits.allocation = Some(1000);
+    let (output, diagnostics) = run_in(fixture, &format!("print len(readFile({path:?}));"));
+    std::fs::remove_file(path).unwrap();
+    assert!(output.is_empty());
+    assert_eq!(
+        diagnostics[0],
+        "Exceeded the allocation limit of 1000 bytes."
+    );
+}
+
+/// Limits a service would run untrusted snippets under.
+fn service_limits() -> Limits {
+    Limits {
+        steps: Some(1_000_000),
+        time: Some(Duration::from_secs(5)),
+        allocation: Some(1 << 20),
+        ..Limits::default()
+    }
+}
+
+#[test]
+fn self_containing_collections_print_and_compare() {
+    let (output, diagnostics) = run_with(
+        service_limits(),
+        "
+        var a = []; push(a, a); print a; print a == a;
+        var m = {}; m[\"self\"] = m; print m; print m == m[\"self\"];
+        ",
+    );
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    assert_eq!(output, ["[[...]]", "true", "{\"self\": {...}}", "true"]);
+}
+
+#[test]
+fn deeply_nested_source_is_a_syntax_error() {
+    let depth = 50_000;
+    let (output, diagnostics) = run_with(
+        service_limits(),
+        &format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+    );
+    assert!(output.is_empty());
+    assert_eq!(
+        diagnostics,
+        ["[line 1] Error at '(': Expression nested too deeply."]
+    );
+}
+
+#[test]
+fn inconsistent_sort_comparators_are_harmless() {
+    let (output, diagnostics) = run_with(
+        service_limits(),
+        "
+        var seed = 1;
+        fun shuffled(_a, _b) { seed = (seed * 75 + 74) % 65537; return seed % 3 - 1; }
+        var xs = [];
+        for (var i = 0; i < 2000; i++) push(xs, i);
+        sort(xs, shuffled);
+        print len(xs);
+        try { sort(xs, fun (_a, _b) { throw \"bad\"; }); } catch (e) { print e; }
+        ",
+    );
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    assert_eq!(output, ["2000", "bad"]);
+}
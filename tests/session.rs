@@ -0,0 +1,217 @@
+//! Saves the globals of one session and restores them into another, through
+//! the API and through the REPL's commands.
+
+mod common;
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    rc::Rc,
+};
+
+use common::Fixture;
+use crafting_interpreters::{sandbox::Capabilities, session, session::Snapshot};
+
+/// A session: its globals and the interpreter they live in. Snapshots
+/// import modules again, which needs the filesystem.
+fn new_session() -> Fixture {
+    let mut session = Fixture::new();
+    session.interpreter.capabilities = Capabilities::ALL;
+    session
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("lox-session-{}-{name}.lox", std::process::id()))
+}
+
+#[test]
+fn values_and_functions_survive_a_round_trip() {
+    let mut before = new_session();
+    before.run(
+        "
+        var number = 1.5;
+        var negative = -2;
+        var nan = 0 * 340282366920938463463374607431768211456;
+        var text = \"hello\";
+        var flag = true;
+        var nothing = nil;
+        var unset;
+        var nested = [1, [2, 3], {\"a\": [4], 5: \"five\"}];
+        fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+        var square = fun (x) { return x * x; };
+        var functions = [fib, square];
+        ",
+    );
+    assert!(
+        before.diagnostics.lines().is_empty(),
+        "{:?}",
+        before.diagnostics.lines()
+    );
+    let snapshot = Snapshot::take(&before.environment);
+    assert!(snapshot.skipped.is_empty(), "{:?}", snapshot.skipped);
+    assert_eq!(snapshot.saved.len(), 11);
+    let path = snapshot_path("round-trip");
+    snapshot.save(&path).unwrap();
+
+    let mut after = new_session();
+    session::restore(
+        &mut after.lox,
+        &mut after.interpreter,
+        &after.environment,
+        &path,
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // Saving the restored session gives the same program again.
+    assert_eq!(Snapshot::take(&after.environment).source, snapshot.source);
+
+    after.run(
+        "
+        print number; print negative; print nan; print text; print flag;
+        print nothing; print nested;
+        print fib(10); print square(4); print functions[0](6);
+        unset = 1; print unset;
+        ",
+    );
+    assert!(
+        after.diagnostics.lines().is_empty(),
+        "{:?}",
+        after.diagnostics.lines()
+    );
+    assert_eq!(
+        after.output.lines(),
+        [
+            "1.5",
+            "-2",
+            "Nan",
+            "hello",
+            "true",
+            "nil",
+            "[1, [2, 3], {\"a\": [4], 5: \"five\"}]",
+            "55",
+            "16",
+            "8",
+            "1",
+        ]
+    );
+}
+
+#[test]
+fn snapshots_are_lox_source() {
+    let mut session = new_session();
+    session.run(
+        "
+        fun add(a, b) { return a + b; }
+        var plus = add;
+        var xs = [1, nil];
+        ",
+    );
+    assert_eq!(
+        Snapshot::take(&session.environment).source,
+        "\
+fun add(a, b) {
+    return a + b;
+}
+var plus = fun (a, b) {
+    return a + b;
+};
+var xs = [1, nil];
+"
+    );
+}
+
+#[test]
+fn values_that_cant_be_written_are_skipped() {
+    let mut session = new_session();
+    session.run(
+        "
+        fun counter() { var count = 0; return fun () { count++; return count; }; }
+        var next = counter();
+        var cycle = [];
+        push(cycle, cycle);
+        var kept = 1;
+        ",
+    );
+    let snapshot = Snapshot::take(&session.environment);
+    let skipped: Vec<_> = snapshot
+        .skipped
+        .iter()
+        .map(|(name, reason)| format!("{}: {reason}", name.name()))
+        .collect();
+    assert_eq!(
+        skipped,
+        [
+            "cycle: it contains itself",
+            "next: it closes over local variables"
+        ]
+    );
+    let saved: Vec<_> = snapshot.saved.iter().map(|name| name.name()).collect();
+    assert_eq!(saved, [Rc::from("counter"), Rc::from("kept")]);
+}
+
+#[test]
+fn modules_are_imported_again() {
+    let lib = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/modules/math.lox");
+    let mut before = new_session();
+    before.run(&format!("import {:?} as math;", lib.to_str().unwrap()));
+    let path = snapshot_path("modules");
+    Snapshot::take(&before.environment).save(&path).unwrap();
+
+    let mut after = new_session();
+    session::restore(
+        &mut after.lox,
+        &mut after.interpreter,
+        &after.environment,
+        &path,
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    after.run("print math.double(math.pi);");
+    assert_eq!(after.output.lines(), ["6"]);
+}
+
+#[test]
+fn restoring_a_missing_file_fails() {
+    let mut session = new_session();
+    let result = session::restore(
+        &mut session.lox,
+        &mut session.interpreter,
+        &session.environment,
+        snapshot_path("missing"),
+    );
+    assert!(result.is_err());
+}
+
+/// Feeds `input` to the REPL and returns what it wrote to standard output.
+fn repl(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_crafting_interpreters"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn repl_saves_and_loads_sessions() {
+    let path = snapshot_path("repl");
+    let path = path.to_str().unwrap();
+    let saved = repl(&format!(
+        "var answer = 42;\nfun twice(x) {{ return 2 * x; }}\n:save {path}\n"
+    ));
+    assert!(saved.contains(&format!("Saved 2 globals to '{path}'.")));
+
+    let loaded = repl(&format!(":load-session {path}\nprint twice(answer);\n"));
+    std::fs::remove_file(path).unwrap();
+    assert!(loaded.contains("84"), "{loaded}");
+}
@@ -0,0 +1,44 @@
+//! `--time` reports each phase of a run to the diagnostics sink.
+
+mod common;
+
+use common::Fixture;
+
+/// The phases reported for `source`, by name.
+fn phases(source: &str, optimize: bool) -> (Vec<String>, Vec<String>) {
+    let mut fixture = Fixture::new();
+    fixture.lox.set_time(true);
+    fixture.lox.set_optimize(optimize);
+    fixture.run(source);
+    let names = fixture
+        .diagnostics
+        .lines()
+        .iter()
+        .filter_map(|line| {
+            let (name, duration) = line.split_once(':')?;
+            duration
+                .trim()
+                .strip_suffix(" ms")?
+                .parse::<f64>()
+                .ok()
+                .map(|_| name.trim().to_string())
+        })
+        .collect();
+    (fixture.output.lines(), names)
+}
+
+#[test]
+fn phases_are_reported_to_diagnostics() {
+    let (output, names) = phases("print 1 + 2;", true);
+    assert_eq!(output, ["3"]);
+    assert_eq!(
+        names,
+        ["scan", "parse", "check", "optimize", "resolve", "execute", "total"]
+    );
+}
+
+#[test]
+fn phases_before_a_compile_error_are_reported() {
+    let (_, names) = phases("print (1;", false);
+    assert_eq!(names, ["scan", "parse", "total"]);
+}